@@ -0,0 +1,245 @@
+use std::io::{Read, Write};
+
+use crate::eval::environment::Environment;
+use crate::eval::evaluator::Evaluator;
+use crate::eval::ObjectWrapper;
+use crate::lexer::lexer::Lexer;
+use crate::parser::program::Program;
+use crate::parser::{diagnostic, format, Parser};
+
+#[cfg(test)]
+mod test;
+
+/// 执行成功
+pub const EXIT_OK: i32 = 0;
+/// 脚本执行时出现了没有处理的运行时错误，或者`fmt --check`发现了没有格式化的文件
+pub const EXIT_FAILURE: i32 = 1;
+/// 源码有语法错误
+pub const EXIT_SYNTAX: i32 = 2;
+/// 命令行参数错误
+pub const EXIT_USAGE: i32 = 64;
+/// 读写文件失败
+pub const EXIT_IO: i32 = 66;
+
+const USAGE: &str = "usage: monkey_rust [COMMAND]
+
+Without COMMAND, starts an interactive REPL.
+
+commands:
+    run FILE [ARGS...]       run a script, ARGS are available as the `args` array
+    eval -e CODE [ARGS...]   evaluate CODE and print the result
+    check FILE...            check the syntax of files without running them
+    fmt [--check] [FILE...]  format source files
+
+exit status:
+    0   success
+    1   uncaught runtime error, or unformatted files with `fmt --check`
+    2   syntax error
+    64  invalid command line arguments
+    66  cannot read or write a file";
+
+const FMT_USAGE: &str = "usage: monkey_rust fmt [--check] [FILE...]
+
+Formats Monkey source files in place. Without FILE, reads from stdin and writes to stdout.
+
+options:
+    --check    don't write anything, exit with 1 if any input is not formatted";
+
+/// 执行命令行中的子命令，返回进程的退出码；没有子命令时返回None，由调用方启动REPL
+pub fn dispatch(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    let code = match command.as_str() {
+        "run" => run(rest),
+        "eval" => eval(rest),
+        "check" => check(rest),
+        "fmt" => fmt(rest),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            EXIT_OK
+        }
+        _ => usage_error(&format!("unknown command `{}`", command), USAGE),
+    };
+    Some(code)
+}
+
+/// `run FILE [ARGS...]`，执行脚本文件
+pub fn run(args: &[String]) -> i32 {
+    let (file, script_args) = match args.split_first() {
+        Some((file, _)) if file == "-h" || file == "--help" => {
+            println!("{}", USAGE);
+            return EXIT_OK;
+        }
+        Some(split) => split,
+        None => return usage_error("missing script file", USAGE),
+    };
+    let source = match read_file(file) {
+        Ok(source) => source,
+        Err(code) => return code,
+    };
+    match execute(&source, file, script_args) {
+        Ok(_) => EXIT_OK,
+        Err(code) => code,
+    }
+}
+
+/// `eval -e CODE [ARGS...]`，执行一段代码并输出结果，结果是null时不输出
+pub fn eval(args: &[String]) -> i32 {
+    let (code, script_args) = match args {
+        [flag, code, rest @ ..] if flag == "-e" => (code, rest),
+        [flag] if flag == "-e" => return usage_error("`-e` requires an argument", USAGE),
+        _ => return usage_error("expected `-e CODE`", USAGE),
+    };
+    match execute(code, "<eval>", script_args) {
+        Ok(ObjectWrapper::Null) => EXIT_OK,
+        Ok(obj) => {
+            println!("{}", obj);
+            EXIT_OK
+        }
+        Err(code) => code,
+    }
+}
+
+/// `check FILE...`，只检查语法，报告每个文件中的所有语法错误
+pub fn check(args: &[String]) -> i32 {
+    if args.is_empty() {
+        return usage_error("missing file to check", USAGE);
+    }
+    let mut ret = EXIT_OK;
+    for file in args {
+        let source = match read_file(file) {
+            Ok(source) => source,
+            Err(code) => {
+                ret = code;
+                continue;
+            }
+        };
+        let mut p = Parser::new(Lexer::with_file(source.as_str(), file.as_str()));
+        let (_, errors) = p.parse_program_recovering();
+        for e in &errors {
+            eprint!("{}", diagnostic::render(e, &source));
+        }
+        if !errors.is_empty() && ret == EXIT_OK {
+            ret = EXIT_SYNTAX;
+        }
+    }
+    ret
+}
+
+/// `fmt [--check] [FILE...]`，格式化源码
+pub fn fmt(args: &[String]) -> i32 {
+    let mut check = false;
+    let mut files = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{}", FMT_USAGE);
+                return EXIT_OK;
+            }
+            _ if arg.starts_with('-') => {
+                return usage_error(&format!("unknown option `{}`", arg), FMT_USAGE);
+            }
+            _ => files.push(arg.as_str()),
+        }
+    }
+
+    if files.is_empty() {
+        let mut source = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut source) {
+            eprintln!("error: cannot read stdin: {}", e);
+            return EXIT_IO;
+        }
+        return match format_source(&source, "<stdin>") {
+            Ok(formatted) if check => {
+                if formatted == source {
+                    EXIT_OK
+                } else {
+                    println!("<stdin>");
+                    EXIT_FAILURE
+                }
+            }
+            Ok(formatted) => {
+                print!("{}", formatted);
+                std::io::stdout().flush().unwrap();
+                EXIT_OK
+            }
+            Err(code) => code,
+        };
+    }
+
+    let mut ret = EXIT_OK;
+    for file in files {
+        let formatted = match read_file(file)
+            .and_then(|source| format_source(&source, file).map(|formatted| (source, formatted)))
+        {
+            Ok((source, formatted)) if source == formatted => continue,
+            Ok((_, formatted)) => formatted,
+            Err(code) => {
+                ret = code;
+                continue;
+            }
+        };
+        if check {
+            // 列出所有需要格式化的文件
+            println!("{}", file);
+            if ret == EXIT_OK {
+                ret = EXIT_FAILURE;
+            }
+        } else if let Err(e) = std::fs::write(file, formatted) {
+            eprintln!("error: cannot write {}: {}", file, e);
+            ret = EXIT_IO;
+        }
+    }
+    ret
+}
+
+/// 执行一段源码，`args`数组中是传给脚本的参数。
+/// 语法错误和运行时错误会输出到stderr，并以对应的退出码作为Err返回
+pub fn execute(source: &str, file: &str, args: &[String]) -> Result<ObjectWrapper, i32> {
+    let program = parse(source, file)?;
+    let mut evaluator = Evaluator::with_env(&program.statements, script_env(args));
+    evaluator.set_file(file);
+    evaluator.eval().map_err(|e| {
+        eprint!("{}", diagnostic::render(&e, source));
+        EXIT_FAILURE
+    })
+}
+
+/// 脚本执行时的全局作用域，其中只有参数数组`args`
+pub fn script_env(args: &[String]) -> Environment {
+    let env = Environment::default();
+    let args = args
+        .iter()
+        .map(|arg| ObjectWrapper::String(arg.clone()))
+        .collect();
+    env.set("args", ObjectWrapper::Array(args));
+    env
+}
+
+fn parse(source: &str, file: &str) -> Result<Program, i32> {
+    Parser::new(Lexer::with_file(source, file))
+        .parse_program()
+        .map_err(|e| {
+            eprint!("{}", diagnostic::render(&e, source));
+            EXIT_SYNTAX
+        })
+}
+
+fn format_source(source: &str, file: &str) -> Result<String, i32> {
+    format::format(source).map_err(|e| {
+        eprint!("{}", diagnostic::render(&e.or_file(Some(file)), source));
+        EXIT_SYNTAX
+    })
+}
+
+fn read_file(file: &str) -> Result<String, i32> {
+    std::fs::read_to_string(file).map_err(|e| {
+        eprintln!("error: cannot read {}: {}", file, e);
+        EXIT_IO
+    })
+}
+
+fn usage_error(info: &str, usage: &str) -> i32 {
+    eprintln!("error: {}\n\n{}", info, usage);
+    EXIT_USAGE
+}
@@ -0,0 +1,30 @@
+use crate::cli::{execute, script_env, EXIT_FAILURE, EXIT_SYNTAX};
+use crate::eval::ObjectWrapper;
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn test_script_args() {
+    let env = script_env(&args(&["a", "b"]));
+    assert_eq!(
+        env.get("args"),
+        Some(ObjectWrapper::Array(vec![
+            ObjectWrapper::String("a".to_string()),
+            ObjectWrapper::String("b".to_string()),
+        ]))
+    );
+
+    let ret = execute("len(args) + len(args[0])", "main.mk", &args(&["hello"]));
+    assert_eq!(ret, Ok(ObjectWrapper::Integer(6)));
+    let ret = execute("args", "main.mk", &[]);
+    assert_eq!(ret, Ok(ObjectWrapper::Array(vec![])));
+}
+
+#[test]
+fn test_exit_code() {
+    assert_eq!(execute("let x = ;", "main.mk", &[]), Err(EXIT_SYNTAX));
+    assert_eq!(execute("1 + foo", "main.mk", &[]), Err(EXIT_FAILURE));
+    assert_eq!(execute("break", "main.mk", &[]), Err(EXIT_FAILURE));
+}
@@ -0,0 +1,181 @@
+use crate::lexer::span::Span;
+use crate::lexer::token::Token;
+use std::fmt::{Debug, Display, Formatter};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// 没有更具体分类的错误，运行时错误也都属于这一类
+    General,
+    /// 期望的是某个token，实际遇到的是另一个
+    UnexpectedToken,
+    /// 当前token不能作为表达式的开头
+    NoPrefixParse,
+    /// 词法分析得到了非法字符
+    IllegalToken,
+    /// 由`throw`语句抛出的错误
+    Thrown,
+}
+
+/// 调用栈中的一层调用，name是函数名，匿名函数为`<anonymous>`，
+/// span和file是调用表达式所在的位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub name: String,
+    pub span: Span,
+    pub file: Option<String>,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{} at {}:{}", self.name, file, self.span),
+            None => write!(f, "{} at {}", self.name, self.span),
+        }
+    }
+}
+
+/// 解析和执行过程中的错误，解析阶段会额外记录期望的token和实际遇到的token，
+/// 运行时错误会记录出错时的调用栈。`throw`抛出的错误kind为Thrown
+#[derive(Clone, PartialEq)]
+pub struct Error(Box<ErrorInner>);
+
+#[derive(Clone, PartialEq)]
+struct ErrorInner {
+    kind: ErrorKind,
+    info: String,
+    span: Option<Span>,
+    file: Option<String>,
+    expected: Option<Token>,
+    found: Option<Token>,
+    help: Vec<String>,
+    // 出错时的调用栈，最内层的调用在前；None表示还没有记录
+    frames: Option<Vec<Frame>>,
+}
+
+impl Error {
+    pub fn new<T: Into<String>>(info: T) -> Self {
+        Error(Box::new(ErrorInner {
+            kind: ErrorKind::General,
+            info: info.into(),
+            span: None,
+            file: None,
+            expected: None,
+            found: None,
+            help: vec![],
+            frames: None,
+        }))
+    }
+
+    pub fn unexpected<T: Into<String>>(info: T, expected: Token, found: Token) -> Self {
+        let mut ret = Error::new(info).with_kind(ErrorKind::UnexpectedToken);
+        ret.0.expected = Some(expected);
+        ret.0.found = Some(found);
+        ret
+    }
+
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.0.kind = kind;
+        self
+    }
+
+    pub fn with_found(mut self, found: Token) -> Self {
+        self.0.found = Some(found);
+        self
+    }
+
+    /// 附加一条提示信息，渲染时以`help:`的形式出现
+    pub fn with_help<T: Into<String>>(mut self, help: T) -> Self {
+        self.0.help.push(help.into());
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.0.kind
+    }
+
+    pub fn info(&self) -> &str {
+        &self.0.info
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.0.span
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        self.0.file.as_deref()
+    }
+
+    pub fn expected(&self) -> Option<&Token> {
+        self.0.expected.as_ref()
+    }
+
+    pub fn found(&self) -> Option<&Token> {
+        self.0.found.as_ref()
+    }
+
+    pub fn help(&self) -> &[String] {
+        &self.0.help
+    }
+
+    /// 出错时的调用栈，最内层的调用在前
+    pub fn frames(&self) -> &[Frame] {
+        self.0.frames.as_deref().unwrap_or(&[])
+    }
+
+    /// 错误还没有位置信息时才设置，保证最内层（最精确）的位置不被覆盖
+    pub fn or_span(mut self, span: Span) -> Self {
+        if self.0.span.is_none() {
+            self.0.span = Some(span);
+        }
+        self
+    }
+
+    /// 和or_span一样只在第一次设置，记录的是最早发现错误时的调用栈。
+    /// stack是从外到内的调用，重新抛出的错误保留原来的调用栈
+    pub fn or_frames(mut self, stack: &[Frame]) -> Self {
+        if self.0.frames.is_none() {
+            self.0.frames = Some(stack.iter().rev().cloned().collect());
+        }
+        self
+    }
+
+    pub fn or_file(mut self, file: Option<&str>) -> Self {
+        if self.0.file.is_none() {
+            self.0.file = file.map(|f| f.to_string());
+        }
+        self
+    }
+}
+
+impl From<&str> for Error {
+    fn from(s: &str) -> Self {
+        Error::new(s)
+    }
+}
+
+impl From<String> for Error {
+    fn from(s: String) -> Self {
+        Error::new(s)
+    }
+}
+
+impl Debug for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.0.file, &self.0.span) {
+            (Some(file), Some(span)) => write!(f, "{}:{}: {}", file, span, self.0.info),
+            (None, Some(span)) => write!(f, "{}: {}", span, self.0.info),
+            (Some(file), None) => write!(f, "{}: {}", file, self.0.info),
+            (None, None) => write!(f, "{}", self.0.info),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
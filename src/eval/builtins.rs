@@ -1,33 +1,38 @@
+use crate::error::Result;
+use crate::eval::hash::{HashKey, HashObject};
 use crate::eval::ObjectWrapper;
-use crate::parser::Result;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::sync::{Arc, Mutex};
 
-impl Into<ObjectWrapper> for bool {
-    fn into(self) -> ObjectWrapper {
-        ObjectWrapper::Boolean(self)
+impl From<bool> for ObjectWrapper {
+    fn from(v: bool) -> Self {
+        ObjectWrapper::Boolean(v)
     }
 }
 
-impl Into<ObjectWrapper> for i64 {
-    fn into(self) -> ObjectWrapper {
-        ObjectWrapper::Integer(self)
+impl From<i64> for ObjectWrapper {
+    fn from(v: i64) -> Self {
+        ObjectWrapper::Integer(v)
     }
 }
 
-impl Into<ObjectWrapper> for f64 {
-    fn into(self) -> ObjectWrapper {
-        ObjectWrapper::Float(self)
+impl From<f64> for ObjectWrapper {
+    fn from(v: f64) -> Self {
+        ObjectWrapper::Float(v)
     }
 }
 
-impl Into<ObjectWrapper> for String {
-    fn into(self) -> ObjectWrapper {
-        ObjectWrapper::String(self)
+impl From<String> for ObjectWrapper {
+    fn from(v: String) -> Self {
+        ObjectWrapper::String(v)
     }
 }
 
+/// 可以接受任意个参数的内置函数的参数个数
+pub const VARIADIC: usize = usize::MAX;
+
 lazy_static! {
     static ref BUILTINS_INS: Builtins = {
         let ret = Builtins {
@@ -46,8 +51,12 @@ lazy_static! {
                     );
                 }
                 match &args[0] {
-                    ObjectWrapper::String(v) => Ok(ObjectWrapper::Integer(v.len() as i64)),
+                    // 字符串的长度是字符数，字节数用byte_len
+                    ObjectWrapper::String(v) => {
+                        Ok(ObjectWrapper::Integer(v.chars().count() as i64))
+                    }
                     ObjectWrapper::Array(array) => Ok(ObjectWrapper::Integer(array.len() as i64)),
+                    ObjectWrapper::Hash(hash) => Ok(ObjectWrapper::Integer(hash.len() as i64)),
                     _ => Err(format!(
                         "Argument to `len` not supported, got {}",
                         args[0].type_str()
@@ -57,6 +66,34 @@ lazy_static! {
             }),
         );
 
+        // 每个参数输出一行，字符串不带引号
+        maps.insert(
+            "puts".to_string(),
+            ObjectWrapper::BuiltinFn(VARIADIC, |args: Vec<ObjectWrapper>| -> Result<ObjectWrapper> {
+                for arg in args {
+                    match arg {
+                        ObjectWrapper::String(v) => println!("{}", v),
+                        obj => println!("{}", obj),
+                    }
+                }
+                Ok(ObjectWrapper::Null)
+            }),
+        );
+
+        maps.insert(
+            "byte_len".to_string(),
+            ObjectWrapper::BuiltinFn(1, |args: Vec<ObjectWrapper>| -> Result<ObjectWrapper> {
+                match &args[0] {
+                    ObjectWrapper::String(v) => Ok(ObjectWrapper::Integer(v.len() as i64)),
+                    _ => Err(format!(
+                        "Argument to `byte_len` must be STRING, got {}",
+                        args[0].type_str()
+                    )
+                    .into()),
+                }
+            }),
+        );
+
         maps.insert(
             "first".to_string(),
             ObjectWrapper::BuiltinFn(1, |args: Vec<ObjectWrapper>| -> Result<ObjectWrapper> {
@@ -67,17 +104,17 @@ lazy_static! {
                 }
 
                 if let ObjectWrapper::Array(array) = &args[0] {
-                    if array.len() > 0 {
+                    if !array.is_empty() {
                         Ok(array[0].clone())
                     } else {
                         Ok(ObjectWrapper::Null)
                     }
                 } else {
-                    return Err(format!(
+                    Err(format!(
                         "Argument to 'first' must be ARRAY, got {:?}",
                         args[0].type_str()
                     )
-                    .into());
+                    .into())
                 }
             }),
         );
@@ -92,17 +129,17 @@ lazy_static! {
                 }
 
                 if let ObjectWrapper::Array(array) = &args[0] {
-                    if array.len() > 0 {
+                    if !array.is_empty() {
                         Ok(array[array.len() - 1].clone())
                     } else {
                         Ok(ObjectWrapper::Null)
                     }
                 } else {
-                    return Err(format!(
+                    Err(format!(
                         "Argument to 'last' must be ARRAY, got {:?}",
                         args[0].type_str()
                     )
-                    .into());
+                    .into())
                 }
             }),
         );
@@ -117,29 +154,85 @@ lazy_static! {
                 }
 
                 if let ObjectWrapper::Array(array) = &args[0] {
-                    if array.len() > 0 {
-                        Ok(ObjectWrapper::Array(
-                            array[0..array.len() - 1]
-                                .iter()
-                                .map(|ele| ele.clone())
-                                .collect(),
-                        ))
+                    if !array.is_empty() {
+                        Ok(ObjectWrapper::Array(array[0..array.len() - 1].to_vec()))
                     } else {
                         Ok(ObjectWrapper::Null)
                     }
                 } else {
-                    return Err(format!(
+                    Err(format!(
                         "Argument to 'rest' must be ARRAY, got {:?}",
                         args[0].type_str()
                     )
-                    .into());
+                    .into())
                 }
             }),
         );
+
+        maps.insert(
+            "keys".to_string(),
+            ObjectWrapper::BuiltinFn(1, |args: Vec<ObjectWrapper>| -> Result<ObjectWrapper> {
+                let hash = expect_hash("keys", &args[0])?;
+                Ok(ObjectWrapper::Array(
+                    hash.keys().map(|k| k.clone().into()).collect(),
+                ))
+            }),
+        );
+
+        maps.insert(
+            "values".to_string(),
+            ObjectWrapper::BuiltinFn(1, |args: Vec<ObjectWrapper>| -> Result<ObjectWrapper> {
+                let hash = expect_hash("values", &args[0])?;
+                Ok(ObjectWrapper::Array(hash.values().cloned().collect()))
+            }),
+        );
+
+        maps.insert(
+            "has_key".to_string(),
+            ObjectWrapper::BuiltinFn(2, |args: Vec<ObjectWrapper>| -> Result<ObjectWrapper> {
+                let hash = expect_hash("has_key", &args[0])?;
+                let key = HashKey::try_from(&args[1])?;
+                Ok(ObjectWrapper::Boolean(hash.contains_key(&key)))
+            }),
+        );
+
+        maps.insert(
+            "delete".to_string(),
+            ObjectWrapper::BuiltinFn(2, |args: Vec<ObjectWrapper>| -> Result<ObjectWrapper> {
+                let mut hash = expect_hash("delete", &args[0])?.clone();
+                let key = HashKey::try_from(&args[1])?;
+                hash.remove(&key);
+                Ok(ObjectWrapper::Hash(hash))
+            }),
+        );
+
+        maps.insert(
+            "merge".to_string(),
+            ObjectWrapper::BuiltinFn(2, |args: Vec<ObjectWrapper>| -> Result<ObjectWrapper> {
+                let mut hash = expect_hash("merge", &args[0])?.clone();
+                for (k, v) in expect_hash("merge", &args[1])?.iter() {
+                    hash.insert(k.clone(), v.clone());
+                }
+                Ok(ObjectWrapper::Hash(hash))
+            }),
+        );
         ret
     };
 }
 
+fn expect_hash<'a>(name: &str, arg: &'a ObjectWrapper) -> Result<&'a HashObject> {
+    if let ObjectWrapper::Hash(hash) = arg {
+        Ok(hash)
+    } else {
+        Err(format!(
+            "Argument to '{}' must be HASH, got {:?}",
+            name,
+            arg.type_str()
+        )
+        .into())
+    }
+}
+
 pub struct Builtins {
     builtins: Arc<Mutex<HashMap<String, ObjectWrapper>>>,
 }
@@ -153,6 +246,16 @@ impl Builtins {
         self.builtins.clone().lock().unwrap().contains_key(ident)
     }
 
+    pub fn names(&self) -> Vec<String> {
+        self.builtins
+            .clone()
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
     // pub fn set(&mut self, ident: &str, obj: ObjectWrapper) -> Option<ObjectWrapper> {
     //     self.builtins
     //         .clone()
@@ -1,25 +1,259 @@
 use crate::eval::ObjectWrapper;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
 
-#[derive(Default, Clone, Debug, PartialEq)]
+/// 变量的作用域链。
+///
+/// Environment本身只是一个共享的引用，clone之后指向的是同一个作用域，因此函数定义时捕获的
+/// Environment能看到之后才加入的绑定（例如函数自身的名字），从而支持递归。
+/// 函数调用和块语句都会创建一个以外层作用域为outer的新作用域，其中的let不会影响外层。
+///
+/// 每个绑定都记录了是否可变，const绑定不能被赋值，也不能在同一作用域中被重新声明。
+///
+/// 闭包捕获了定义它的作用域，而这个作用域中又保存着闭包本身，形成的引用环不会被Arc释放，
+/// 所以离开块和函数调用的作用域时要调用[`Environment::release`]。
+#[derive(Clone, Default)]
 pub struct Environment {
-    identifiers: HashMap<String, ObjectWrapper>,
+    scope: Arc<Mutex<Scope>>,
+}
+
+#[derive(Default)]
+struct Scope {
+    identifiers: HashMap<String, Binding>,
+    outer: Option<Environment>,
+}
+
+struct Binding {
+    value: ObjectWrapper,
+    mutable: bool,
+}
+
+/// 赋值或声明失败的原因
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AssignError {
+    /// 作用域链上没有这个名字
+    Undeclared,
+    /// 名字是const绑定
+    Constant,
 }
 
 impl Environment {
-    pub fn contains(&self, name: &str) -> bool {
-        self.identifiers.contains_key(name)
+    /// 创建一个以outer为外层作用域的新作用域
+    pub fn new_enclosed(outer: &Environment) -> Self {
+        Environment {
+            scope: Arc::new(Mutex::new(Scope {
+                identifiers: HashMap::default(),
+                outer: Some(outer.clone()),
+            })),
+        }
+    }
+
+    /// 沿着作用域链查找name，返回最内层绑定的值
+    pub fn get(&self, name: &str) -> Option<ObjectWrapper> {
+        let scope = self.scope.lock().unwrap();
+        match scope.identifiers.get(name) {
+            Some(binding) => Some(binding.value.clone()),
+            None => scope.outer.as_ref().and_then(|outer| outer.get(name)),
+        }
+    }
+
+    /// 在当前作用域中绑定一个可变的name，不影响外层作用域中的同名绑定
+    pub fn set(&self, name: &str, obj: ObjectWrapper) -> Option<ObjectWrapper> {
+        self.scope
+            .lock()
+            .unwrap()
+            .identifiers
+            .insert(
+                name.to_string(),
+                Binding {
+                    value: obj,
+                    mutable: true,
+                },
+            )
+            .map(|binding| binding.value)
+    }
+
+    /// let/const声明，当前作用域中已有同名的const绑定时失败
+    pub fn define(
+        &self,
+        name: &str,
+        obj: ObjectWrapper,
+        mutable: bool,
+    ) -> std::result::Result<(), AssignError> {
+        let mut scope = self.scope.lock().unwrap();
+        if scope.identifiers.get(name).is_some_and(|b| !b.mutable) {
+            return Err(AssignError::Constant);
+        }
+        scope.identifiers.insert(
+            name.to_string(),
+            Binding {
+                value: obj,
+                mutable,
+            },
+        );
+        Ok(())
+    }
+
+    /// 修改作用域链上最内层的name绑定
+    pub fn assign(&self, name: &str, obj: ObjectWrapper) -> std::result::Result<(), AssignError> {
+        let mut scope = self.scope.lock().unwrap();
+        if let Some(binding) = scope.identifiers.get_mut(name) {
+            if !binding.mutable {
+                return Err(AssignError::Constant);
+            }
+            binding.value = obj;
+            return Ok(());
+        }
+        match &scope.outer {
+            Some(outer) => outer.assign(name, obj),
+            None => Err(AssignError::Undeclared),
+        }
     }
 
-    pub fn get(&self, name: &str) -> Option<&ObjectWrapper> {
-        self.identifiers.get(name)
+    /// 离开作用域时调用。作用域中的闭包没有逃逸到作用域之外时，作用域只被这些闭包引用，
+    /// 清空其中的绑定来打破引用环；仍然被外面引用的作用域保持不变。
+    ///
+    /// 做法是在作用域以及从其中的值能到达的、以它为外层的作用域中统计相互之间的引用，
+    /// 引用计数比这个数多的作用域被外面引用着，从它们能到达的作用域都不能清空
+    pub fn release(self) {
+        // 没有闭包捕获这个作用域，直接释放
+        if Arc::strong_count(&self.scope) == 1 {
+            return;
+        }
+
+        let root = Arc::as_ptr(&self.scope);
+        let mut nodes = vec![self];
+        let mut index = HashMap::from([(root, 0)]);
+        let mut edges: Vec<Vec<usize>> = vec![];
+        let mut internal = vec![0];
+        let mut i = 0;
+        while i < nodes.len() {
+            let mut found = vec![];
+            {
+                let scope = nodes[i].scope.lock().unwrap();
+                for binding in scope.identifiers.values() {
+                    collect_environments(&binding.value, &mut found);
+                }
+                found.extend(scope.outer.clone());
+            }
+
+            let mut targets = vec![];
+            for env in found {
+                let ptr = Arc::as_ptr(&env.scope);
+                let j = match index.get(&ptr) {
+                    Some(j) => *j,
+                    // 不在root之内的作用域没有被统计，对它们的引用都算作外面的引用
+                    None if env.has_ancestor(root) => {
+                        index.insert(ptr, nodes.len());
+                        nodes.push(env);
+                        internal.push(0);
+                        nodes.len() - 1
+                    }
+                    None => continue,
+                };
+                internal[j] += 1;
+                targets.push(j);
+            }
+            edges.push(targets);
+            i += 1;
+        }
+
+        // nodes中的每个作用域自己还持有一个引用
+        let mut alive = HashSet::new();
+        let mut pending = (0..nodes.len())
+            .filter(|&j| Arc::strong_count(&nodes[j].scope) > internal[j] + 1)
+            .collect::<Vec<usize>>();
+        while let Some(j) = pending.pop() {
+            if alive.insert(j) {
+                pending.extend(&edges[j]);
+            }
+        }
+        if alive.contains(&0) {
+            return;
+        }
+        for (j, env) in nodes.iter().enumerate() {
+            if !alive.contains(&j) {
+                // 在锁之外释放绑定，其中的值可能持有其他作用域
+                let identifiers = std::mem::take(&mut env.scope.lock().unwrap().identifiers);
+                drop(identifiers);
+            }
+        }
+    }
+
+    // 沿着outer链是否能到达ancestor，自己也算
+    fn has_ancestor(&self, ancestor: *const Mutex<Scope>) -> bool {
+        let mut env = self.clone();
+        loop {
+            if Arc::as_ptr(&env.scope) == ancestor {
+                return true;
+            }
+            let outer = env.scope.lock().unwrap().outer.clone();
+            match outer {
+                Some(outer) => env = outer,
+                None => return false,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.scope)
     }
 
-    pub fn get_mut(&mut self, name: &str) -> Option<&mut ObjectWrapper> {
-        self.identifiers.get_mut(name)
+    /// 作用域链上所有可见的名字
+    pub fn names(&self) -> Vec<String> {
+        let scope = self.scope.lock().unwrap();
+        let mut ret = scope
+            .outer
+            .as_ref()
+            .map(|outer| outer.names())
+            .unwrap_or_default();
+        for name in scope.identifiers.keys() {
+            if !ret.contains(name) {
+                ret.push(name.clone());
+            }
+        }
+        ret
     }
+}
+
+// 值中直接或间接持有的作用域，也就是其中的闭包捕获的作用域
+fn collect_environments(value: &ObjectWrapper, ret: &mut Vec<Environment>) {
+    match value {
+        ObjectWrapper::FunctionObject(_, _, _, env, _) => ret.push(env.clone()),
+        ObjectWrapper::Array(array) => {
+            for element in array {
+                collect_environments(element, ret);
+            }
+        }
+        ObjectWrapper::Hash(hash) => {
+            for element in hash.values() {
+                collect_environments(element, ret);
+            }
+        }
+        ObjectWrapper::ReturnValue(v) => collect_environments(v, ret),
+        _ => {}
+    }
+}
+
+// 两个Environment指向同一个作用域时才相等，逐个比较会在闭包形成的引用环上无限递归
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.scope, &other.scope)
+    }
+}
 
-    pub fn set(&mut self, name: &str, obj: ObjectWrapper) -> Option<ObjectWrapper> {
-        self.identifiers.insert(name.to_string(), obj)
+// 理由同上，只输出当前作用域中的名字
+impl Debug for Environment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let scope = self.scope.lock().unwrap();
+        let mut names = scope.identifiers.keys().collect::<Vec<&String>>();
+        names.sort();
+        f.debug_struct("Environment")
+            .field("names", &names)
+            .field("has_outer", &scope.outer.is_some())
+            .finish()
     }
 }
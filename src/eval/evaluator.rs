@@ -1,72 +1,220 @@
-use crate::eval::builtins::Builtins;
-use crate::eval::environment::Environment;
+use crate::error::{Error, ErrorKind, Frame, Result};
+use crate::eval::builtins::{Builtins, VARIADIC};
+use crate::eval::environment::{AssignError, Environment};
+use crate::eval::hash::{HashKey, HashObject};
 use crate::eval::ObjectWrapper;
-use crate::lexer::token::Token;
-use crate::parser::program::{Expression, Ident, Statement};
-use crate::parser::Result;
+use crate::lexer::span::Span;
+use crate::lexer::token::{Token, KEYWORDS};
+use crate::parser::diagnostic::suggest;
+use crate::parser::program::{
+    join_parameters, Expression, ExpressionKind, Ident, Parameter, Statement, StatementKind,
+    TemplateSegment,
+};
+use std::convert::TryFrom;
 use std::sync::Arc;
 
+/// 函数调用的最大嵌套层数，超过时报告可以被catch捕获的运行时错误，而不是让解释器栈溢出
+pub const MAX_CALL_DEPTH: usize = 1000;
+
 pub struct Evaluator<'a> {
-    statements: &'a Vec<Statement>,
+    statements: &'a [Statement],
     env: Environment,
+    // 正在执行的代码所在的文件，调用函数时切换为定义函数的文件
+    file: Option<Arc<str>>,
+    // 正在执行的函数调用，最外层在前
+    stack: Vec<Frame>,
 }
 
 impl<'a> Evaluator<'a> {
-    pub fn new(statements: &'a Vec<Statement>) -> Self {
+    pub fn with_env(statements: &'a [Statement], env: Environment) -> Self {
         Evaluator {
             statements,
-            env: Environment::default(),
+            env,
+            file: None,
+            stack: vec![],
         }
     }
 
-    pub fn with_env(statements: &'a Vec<Statement>, env: Environment) -> Self {
-        Evaluator { statements, env }
+    /// 设置被执行代码所在的文件名，运行时错误会以`file:line:col`的形式报告
+    pub fn set_file<T: Into<String>>(&mut self, file: T) {
+        self.file = Some(Arc::from(file.into()));
     }
 
     pub fn get_env(self) -> Environment {
-        return self.env;
+        self.env
     }
 
+    /// 执行所有语句。没有被catch捕获的运行时错误以Err的形式返回，其中带有出错的位置；
+    /// 脚本中捕获后作为结果的错误是Ok中的ErrorObject，两者不会混淆
     pub fn eval(&mut self) -> Result<ObjectWrapper> {
-        match self.eval_statements(&self.statements) {
-            Err(e) => Ok(ObjectWrapper::ErrorObject(e.to_string())),
-            Ok(ret) => Ok(ret),
-        }
+        self.eval_statements(self.statements)
+            .map_err(|e| e.or_file(self.file.as_deref()))
     }
 
-    fn eval_statements(&mut self, statements: &Vec<Statement>) -> Result<ObjectWrapper> {
+    fn eval_statements(&mut self, statements: &[Statement]) -> Result<ObjectWrapper> {
+        self.hoist_functions(statements)?;
         let mut ret = ObjectWrapper::Null;
         for st in statements {
             ret = self.eval_statement(st)?;
             log::trace!("eval_statement result: {:}", ret);
-            if let ObjectWrapper::ReturnValue(v) = ret {
-                return Ok(*v);
+            match ret {
+                ObjectWrapper::ReturnValue(v) => return Ok(*v),
+                ObjectWrapper::Break | ObjectWrapper::Continue => {
+                    return Err(
+                        Error::new(format!("'{}' outside of a loop", ret.type_str()))
+                            .or_span(st.span),
+                    );
+                }
+                _ => {}
             }
         }
         Ok(ret)
     }
 
-    fn eval_block_statements(&mut self, statements: &Vec<Statement>) -> Result<ObjectWrapper> {
+    fn eval_block_statements(&mut self, statements: &[Statement]) -> Result<ObjectWrapper> {
+        self.hoist_functions(statements)?;
         let mut ret = ObjectWrapper::Null;
         for st in statements {
             ret = self.eval_statement(st)?;
-            if let ObjectWrapper::ReturnValue(_) = ret {
+            if let ObjectWrapper::ReturnValue(_) | ObjectWrapper::Break | ObjectWrapper::Continue =
+                ret
+            {
                 break;
             }
         }
         Ok(ret)
     }
 
+    // 函数声明会被提升：执行语句之前先绑定其中声明的所有函数，
+    // 因此函数可以在声明之前被调用，顶层函数之间也可以相互递归
+    fn hoist_functions(&mut self, statements: &[Statement]) -> Result<()> {
+        for st in statements {
+            if let StatementKind::FunctionStatement(name, params, body) = &st.kind {
+                let func = ObjectWrapper::FunctionObject(
+                    Some(name.0.clone()),
+                    params.clone(),
+                    body.clone(),
+                    self.env.clone(),
+                    self.file.clone(),
+                );
+                if self.env.define(&name.0, func, true).is_err() {
+                    return Err(Error::new(format!("cannot redeclare constant: {}", name.0))
+                        .or_span(st.span));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 块语句在新的作用域中执行，其中的let不会泄漏到外层
+    fn eval_scoped_block(&mut self, statements: &[Statement]) -> Result<ObjectWrapper> {
+        let env = Environment::new_enclosed(&self.env);
+        self.eval_block_in(env, statements)
+    }
+
+    fn eval_block_in(
+        &mut self,
+        env: Environment,
+        statements: &[Statement],
+    ) -> Result<ObjectWrapper> {
+        let outer = std::mem::replace(&mut self.env, env);
+        let ret = self.eval_block_statements(statements);
+        std::mem::replace(&mut self.env, outer).release();
+        ret
+    }
+
     fn eval_statement(&mut self, statement: &Statement) -> Result<ObjectWrapper> {
-        match statement {
-            Statement::ReturnStatement(expr) => self.eval_return_statement(expr),
-            Statement::ExpressionStatement(expr) => self.eval_expression(expr),
-            Statement::LetStatement(ident, expression) => {
-                let value = self.eval_expression(expression)?;
-                self.env.set(&ident.0, value);
-                Ok(ObjectWrapper::Null)
-            } // _ => Ok(ObjectWrapper::Null),
+        match &statement.kind {
+            StatementKind::ReturnStatement(expr) => self.eval_return_statement(expr),
+            StatementKind::ExpressionStatement(expr) => self.eval_expression(expr),
+            StatementKind::LetStatement(ident, expression) => {
+                self.eval_declaration(ident, expression, true)
+            }
+            StatementKind::ConstStatement(ident, expression) => {
+                self.eval_declaration(ident, expression, false)
+            }
+            StatementKind::WhileStatement(condition, body) => {
+                self.eval_while_statement(condition, body)
+            }
+            StatementKind::ForStatement(ident, iterable, body) => {
+                self.eval_for_statement(ident, iterable, body)
+            }
+            StatementKind::BreakStatement => Ok(ObjectWrapper::Break),
+            StatementKind::ContinueStatement => Ok(ObjectWrapper::Continue),
+            // 已经在hoist_functions中绑定
+            StatementKind::FunctionStatement(_, _, _) => Ok(ObjectWrapper::Null),
+            StatementKind::ThrowStatement(expr) => self.eval_throw_statement(expr),
+            StatementKind::TryStatement(body, catch, finally) => {
+                self.eval_try_statement(body, catch.as_ref(), finally.as_deref())
+            }
+        }
+        .map_err(|e| {
+            e.or_span(statement.span)
+                .or_file(self.file.as_deref())
+                .or_frames(&self.stack)
+        })
+    }
+
+    fn eval_declaration(
+        &mut self,
+        ident: &Ident,
+        expression: &Expression,
+        mutable: bool,
+    ) -> Result<ObjectWrapper> {
+        let value = match self.eval_expression(expression)? {
+            // `let f = fn() {}`中的匿名函数以f命名
+            ObjectWrapper::FunctionObject(None, params, body, env, file) => {
+                ObjectWrapper::FunctionObject(Some(ident.0.clone()), params, body, env, file)
+            }
+            value => value,
+        };
+        match self.env.define(&ident.0, value, mutable) {
+            Ok(()) => Ok(ObjectWrapper::Null),
+            Err(_) => Err(format!("cannot redeclare constant: {}", ident.0).into()),
+        }
+    }
+
+    fn eval_while_statement(
+        &mut self,
+        condition: &Expression,
+        body: &[Statement],
+    ) -> Result<ObjectWrapper> {
+        loop {
+            match self.eval_expression(condition)? {
+                ObjectWrapper::Boolean(true) => {}
+                ObjectWrapper::Boolean(false) => break,
+                _ => return Err(Error::new("Invalid 'while' condition.").or_span(condition.span)),
+            }
+            match self.eval_scoped_block(body)? {
+                ObjectWrapper::Break => break,
+                ret @ ObjectWrapper::ReturnValue(_) => return Ok(ret),
+                _ => {}
+            }
         }
+        Ok(ObjectWrapper::Null)
+    }
+
+    // 每次迭代都在新的作用域中绑定循环变量，闭包捕获到的是当次迭代的值
+    fn eval_for_statement(
+        &mut self,
+        ident: &Ident,
+        iterable: &Expression,
+        body: &[Statement],
+    ) -> Result<ObjectWrapper> {
+        let items = self
+            .eval_expression(iterable)?
+            .iterate()
+            .map_err(|e| e.or_span(iterable.span))?;
+        for item in items {
+            let env = Environment::new_enclosed(&self.env);
+            env.set(&ident.0, item);
+            match self.eval_block_in(env, body)? {
+                ObjectWrapper::Break => break,
+                ret @ ObjectWrapper::ReturnValue(_) => return Ok(ret),
+                _ => {}
+            }
+        }
+        Ok(ObjectWrapper::Null)
     }
 
     fn eval_return_statement(&mut self, expression: &Expression) -> Result<ObjectWrapper> {
@@ -74,73 +222,312 @@ impl<'a> Evaluator<'a> {
         Ok(ObjectWrapper::ReturnValue(Box::new(ret)))
     }
 
+    // 字符串作为错误信息抛出，捕获到的错误原样重新抛出，保留原来的位置和调用
+    fn eval_throw_statement(&mut self, expression: &Expression) -> Result<ObjectWrapper> {
+        match self.eval_expression(expression)? {
+            ObjectWrapper::String(message) => Err(Error::new(message).with_kind(ErrorKind::Thrown)),
+            ObjectWrapper::ErrorObject(err) => Err(err),
+            obj => Err(Error::new(format!(
+                "throw expects a string or an error, got {}",
+                obj.type_str()
+            ))
+            .or_span(expression.span)),
+        }
+    }
+
+    // try和catch块的结果是整个语句的值；finally总会执行，
+    // 其中的return、break和continue会取代前面的结果，包括还没有被捕获的错误
+    fn eval_try_statement(
+        &mut self,
+        body: &[Statement],
+        catch: Option<&(Ident, Vec<Statement>)>,
+        finally: Option<&[Statement]>,
+    ) -> Result<ObjectWrapper> {
+        let mut ret = self.eval_scoped_block(body);
+        if let (Err(err), Some((ident, handler))) = (&ret, catch) {
+            let err = err.clone().or_file(self.file.as_deref());
+            let env = Environment::new_enclosed(&self.env);
+            env.set(&ident.0, ObjectWrapper::ErrorObject(err));
+            ret = self.eval_block_in(env, handler);
+        }
+        if let Some(finally) = finally {
+            let jump = self.eval_scoped_block(finally)?;
+            if let ObjectWrapper::ReturnValue(_) | ObjectWrapper::Break | ObjectWrapper::Continue =
+                jump
+            {
+                return Ok(jump);
+            }
+        }
+        ret
+    }
+
     fn eval_expression(&mut self, expression: &Expression) -> Result<ObjectWrapper> {
+        self.eval_expression_kind(&expression.kind, expression.span)
+            .map_err(|e| {
+                e.or_span(expression.span)
+                    .or_file(self.file.as_deref())
+                    .or_frames(&self.stack)
+            })
+    }
+
+    fn eval_expression_kind(
+        &mut self,
+        expression: &ExpressionKind,
+        span: Span,
+    ) -> Result<ObjectWrapper> {
         match expression {
-            Expression::Identifier(ident) => self.eval_identifier(&ident.0),
-            Expression::IntLiteral(v) => Ok(ObjectWrapper::Integer(v.clone())),
-            Expression::BoolLiteral(v) => Ok(ObjectWrapper::Boolean(v.clone())),
-            Expression::StringLiteral(v) => Ok(ObjectWrapper::String(v.clone())),
-            Expression::InfixExpression(left, operator, right) => {
+            ExpressionKind::Identifier(ident) => self.eval_identifier(&ident.0),
+            ExpressionKind::IntLiteral(v) => Ok(ObjectWrapper::Integer(*v)),
+            ExpressionKind::FloatLiteral(v) => Ok(ObjectWrapper::Float(*v)),
+            ExpressionKind::BoolLiteral(v) => Ok(ObjectWrapper::Boolean(*v)),
+            ExpressionKind::StringLiteral(v) => Ok(ObjectWrapper::String(v.clone())),
+            ExpressionKind::NullLiteral => Ok(ObjectWrapper::Null),
+            ExpressionKind::InfixExpression(left, operator, right) => {
                 self.eval_infix_expression(left, operator, right)
             }
-            Expression::PrefixExpression(operator, right) => {
+            ExpressionKind::PrefixExpression(operator, right) => {
                 self.eval_prefix_expression(operator, right)
             }
-            Expression::IfExpression(condition, consequence, alternative) => {
+            ExpressionKind::IfExpression(condition, consequence, alternative) => {
                 self.eval_if_expression(condition, consequence, alternative)
             }
-            Expression::FunctionExpression(params, body) => Ok(ObjectWrapper::FunctionObject(
-                Arc::new(params.clone()),
-                Arc::new(body.clone()),
+            ExpressionKind::FunctionExpression(params, body) => Ok(ObjectWrapper::FunctionObject(
+                None,
+                params.clone(),
+                body.clone(),
                 self.env.clone(),
+                self.file.clone(),
             )),
-            Expression::CallExpression(func, params) => self.eval_call_expression(func, params),
-            Expression::ArrayLiteral(array) => {
+            ExpressionKind::CallExpression(func, params) => {
+                self.eval_call_expression(func, params, span)
+            }
+            ExpressionKind::ArrayLiteral(array) => {
                 let elements = array
                     .iter()
                     .map(|expr| self.eval_expression(expr))
                     .collect::<Result<Vec<ObjectWrapper>>>()?;
                 Ok(ObjectWrapper::Array(elements))
             }
-            Expression::IndexExpression(array, index) => {
+            ExpressionKind::HashLiteral(pairs) => {
+                let mut hash = HashObject::default();
+                for (key, value) in pairs {
+                    let key_obj = self.eval_expression(key)?;
+                    let hash_key = HashKey::try_from(&key_obj).map_err(|e| e.or_span(key.span))?;
+                    let value = self.eval_expression(value)?;
+                    hash.insert(hash_key, value);
+                }
+                Ok(ObjectWrapper::Hash(hash))
+            }
+            ExpressionKind::IndexExpression(array, index) => {
                 let array = self.eval_expression(array)?;
                 let index = self.eval_expression(index)?;
                 array.index(&index)
-            },
-            _ => Ok(ObjectWrapper::Null),
+            }
+            ExpressionKind::AssignExpression(target, operator, value) => {
+                self.eval_assign_expression(target, operator, value)
+            }
+            ExpressionKind::TemplateLiteral(segments) => {
+                let mut ret = String::new();
+                for segment in segments {
+                    match segment {
+                        TemplateSegment::Text(text) => ret.push_str(text),
+                        // 字符串直接拼接，其余的值使用Display的输出
+                        TemplateSegment::Expression(expr) => match self.eval_expression(expr)? {
+                            ObjectWrapper::String(v) => ret.push_str(&v),
+                            obj => ret.push_str(&obj.to_string()),
+                        },
+                    }
+                }
+                Ok(ObjectWrapper::String(ret))
+            }
+            ExpressionKind::SpreadExpression(_) | ExpressionKind::NamedArgument(_, _) => {
+                Err("spread and named arguments are only allowed in function calls".into())
+            }
         }
     }
 
     fn eval_identifier(&mut self, ident: &str) -> Result<ObjectWrapper> {
-        if self.env.contains(ident) {
-            Ok(self.env.get(ident).unwrap().clone())
+        if let Some(obj) = self.env.get(ident) {
+            Ok(obj)
         } else if Builtins::instance_ref().contains(ident) {
             Ok(Builtins::instance_ref().get(ident).unwrap())
         } else {
-            Err(format!("identifier not found: {}", ident).into())
+            let mut err = Error::new(format!("identifier not found: {}", ident));
+            let builtins = Builtins::instance_ref().names();
+            let names = self.env.names();
+            // `lett x = 1`这样关键字拼写错误的代码也会在这里报错
+            let candidates = names
+                .iter()
+                .chain(builtins.iter())
+                .map(|s| s.as_str())
+                .chain(KEYWORDS.iter().copied());
+            if let Some(name) = suggest(ident, candidates) {
+                err = err.with_help(format!("did you mean `{}`?", name));
+            }
+            Err(err)
         }
     }
 
+    // 目标中的下标只求值一次，复合赋值读取和写回的是同一个位置
+    fn eval_assign_expression(
+        &mut self,
+        target: &Expression,
+        operator: &Token,
+        value: &Expression,
+    ) -> Result<ObjectWrapper> {
+        let mut path = vec![];
+        let (ident, span) = self.eval_assign_target(target, &mut path)?;
+        // 从外到内的每一层容器，最后一个是要读写的下标所在的容器
+        let mut containers = vec![];
+        if let Some((_, init)) = path.split_last() {
+            let mut container = self
+                .eval_identifier(&ident.0)
+                .map_err(|e| e.or_span(span))?;
+            for (index, span) in init {
+                let next = container.index(index).map_err(|e| e.or_span(*span))?;
+                containers.push(container);
+                container = next;
+            }
+            containers.push(container);
+        }
+
+        let value = match operator {
+            Token::Assign => self.eval_expression(value)?,
+            _ => {
+                let current = match (containers.last(), path.last()) {
+                    (Some(container), Some((index, span))) => {
+                        container.index(index).map_err(|e| e.or_span(*span))?
+                    }
+                    _ => self
+                        .eval_identifier(&ident.0)
+                        .map_err(|e| e.or_span(span))?,
+                };
+                let value = self.eval_expression(value)?;
+                match operator {
+                    Token::PlusAssign => current.add(&value),
+                    Token::MinusAssign => current.sub(&value),
+                    Token::AsteriskAssign => current.multi(&value),
+                    Token::SlashAssign => current.divide(&value),
+                    _ => unreachable!(),
+                }?
+            }
+        };
+
+        // 数组和Hash都是值，`a[i][j] = v`需要逐层生成新的容器，最后写回a所在的作用域
+        let mut obj = value.clone();
+        for (container, (index, span)) in containers.iter().zip(path.iter()).rev() {
+            obj = container
+                .set_index(index, obj)
+                .map_err(|e| e.or_span(*span))?;
+        }
+        self.assign_variable(ident, span, obj)?;
+        Ok(value)
+    }
+
+    // 赋值目标最外层的变量，path中依次是每一层的下标和下标表达式的位置
+    fn eval_assign_target<'t>(
+        &mut self,
+        target: &'t Expression,
+        path: &mut Vec<(ObjectWrapper, Span)>,
+    ) -> Result<(&'t Ident, Span)> {
+        match &target.kind {
+            ExpressionKind::Identifier(ident) => Ok((ident, target.span)),
+            ExpressionKind::IndexExpression(container, index) => {
+                let ret = self.eval_assign_target(container, path)?;
+                let index = self.eval_expression(index)?;
+                path.push((index, target.span));
+                Ok(ret)
+            }
+            _ => {
+                Err(Error::new(format!("invalid assignment target: {}", target))
+                    .or_span(target.span))
+            }
+        }
+    }
+
+    fn assign_variable(&mut self, ident: &Ident, span: Span, value: ObjectWrapper) -> Result<()> {
+        match self.env.assign(&ident.0, value) {
+            Ok(()) => return Ok(()),
+            Err(AssignError::Constant) => {
+                return Err(
+                    Error::new(format!("cannot assign to constant: {}", ident.0))
+                        .or_span(span)
+                        .with_help("declared with `const`; use `let` for bindings that change"),
+                )
+            }
+            Err(AssignError::Undeclared) => {}
+        }
+        let mut err = Error::new(format!("assignment to undeclared variable: {}", ident.0));
+        let names = self.env.names();
+        if let Some(name) = suggest(&ident.0, names.iter().map(|s| s.as_str())) {
+            err = err.with_help(format!("did you mean `{}`?", name));
+        } else {
+            err = err.with_help(format!("declare it first with `let {} = ...`", ident.0));
+        }
+        Err(err.or_span(span))
+    }
+
     fn eval_infix_expression(
         &mut self,
         left: &Expression,
         operator: &Token,
         right: &Expression,
     ) -> Result<ObjectWrapper> {
+        if let Token::And | Token::Or = operator {
+            return self.eval_logical_expression(left, operator, right);
+        }
+
         let left = self.eval_expression(left)?;
         let right = self.eval_expression(right)?;
 
         match operator {
             Token::Plus => left.add(&right),
+            Token::Minus => left.sub(&right),
             Token::Eq => left.eq(&right),
             Token::GT => left.great_than(&right),
             Token::LT => left.less_than(&right),
+            Token::GTE => left.great_equal(&right),
+            Token::LTE => left.less_equal(&right),
             Token::NotEq => left.not_eq(&right),
             Token::Asterisk => left.multi(&right),
+            Token::Slash => left.divide(&right),
+            Token::TildeSlash => left.floor_divide(&right),
+            Token::Percent => left.modulo(&right),
+            Token::DoubleAsterisk => left.power(&right),
+            Token::DotDot => left.range(&right),
             _ => Ok(ObjectWrapper::Null),
         }
     }
 
+    // '&&'和'||'是短路求值的，左边已经能决定结果时不再对右边求值
+    fn eval_logical_expression(
+        &mut self,
+        left: &Expression,
+        operator: &Token,
+        right: &Expression,
+    ) -> Result<ObjectWrapper> {
+        let left = self.eval_logical_operand(left, operator)?;
+        match (operator, left) {
+            (Token::And, false) => Ok(ObjectWrapper::Boolean(false)),
+            (Token::Or, true) => Ok(ObjectWrapper::Boolean(true)),
+            _ => Ok(ObjectWrapper::Boolean(
+                self.eval_logical_operand(right, operator)?,
+            )),
+        }
+    }
+
+    fn eval_logical_operand(&mut self, expr: &Expression, operator: &Token) -> Result<bool> {
+        match self.eval_expression(expr)? {
+            ObjectWrapper::Boolean(v) => Ok(v),
+            obj => Err(Error::new(format!(
+                "'{}' expects bool operands, got {}",
+                operator,
+                obj.type_str()
+            ))
+            .or_span(expr.span)),
+        }
+    }
+
     fn eval_prefix_expression(
         &mut self,
         operator: &Token,
@@ -152,11 +539,7 @@ impl<'a> Evaluator<'a> {
                 ObjectWrapper::Integer(v) => Ok(ObjectWrapper::Boolean(v == 0)),
                 _ => Ok(ObjectWrapper::Boolean(false)),
             },
-            Token::Minus => match self.eval_expression(expr)? {
-                ObjectWrapper::Integer(v) => Ok(ObjectWrapper::Integer(-v)),
-                ObjectWrapper::Float(f) => Ok(ObjectWrapper::Float(-f)),
-                _ => Err(format!("cannot eval {} after '-'.", operator.to_string()).into()),
-            },
+            Token::Minus => self.eval_expression(expr)?.negate(),
             _ => Ok(ObjectWrapper::Null),
         }
     }
@@ -164,83 +547,201 @@ impl<'a> Evaluator<'a> {
     fn eval_if_expression(
         &mut self,
         condition: &Expression,
-        consequence: &Vec<Statement>,
-        alternative: &Vec<Statement>,
+        consequence: &[Statement],
+        alternative: &[Statement],
     ) -> Result<ObjectWrapper> {
         let cond = self.eval_expression(condition)?;
         if let ObjectWrapper::Boolean(v) = cond {
             if v {
-                self.eval_block_statements(consequence)
+                self.eval_scoped_block(consequence)
             } else {
-                self.eval_block_statements(alternative)
+                self.eval_scoped_block(alternative)
             }
         } else {
-            return Err("Invalid 'if' condition.".into());
+            Err("Invalid 'if' condition.".into())
         }
     }
 
     fn eval_call_expression(
         &mut self,
         func: &Expression,
-        params: &Vec<Expression>,
+        args: &[Expression],
+        span: Span,
     ) -> Result<ObjectWrapper> {
-        let real_params = params
-            .iter()
-            .map(|expr| self.eval_expression(expr))
-            .collect::<Result<Vec<ObjectWrapper>>>()?;
-        match func {
-            Expression::Identifier(ident) => {
-                let obj = self.eval_identifier(&ident.0)?;
-                match obj {
-                    ObjectWrapper::FunctionObject(params_ident, body, env_func) => {
-                        let params_ident = params_ident.clone();
-                        let body = body.clone();
-                        let env = env_func.clone();
-                        self.do_eval_function_call(&params_ident, &real_params, &body, env)
-                    }
-                    ObjectWrapper::BuiltinFn(nums, func) => {
-                        if real_params.len() != nums {
-                            return Err(format!(
-                                "Wrong number of arguments, expect {} got {}",
-                                nums,
-                                real_params.len()
-                            )
-                            .into());
-                        }
-                        func(real_params)
-                    }
-                    _ => Err(format!("function not found: {}", &ident.0).into()),
+        // 被调用的可以是任何求值结果为函数的表达式，例如`fns[0](x)`、`adder(1)(2)`
+        let callee = self.eval_expression(func)?;
+        let mut positional = vec![];
+        let mut named = vec![];
+        for arg in args {
+            match &arg.kind {
+                ExpressionKind::SpreadExpression(expr) => {
+                    let items = self
+                        .eval_expression(expr)?
+                        .iterate()
+                        .map_err(|e| e.or_span(expr.span))?;
+                    positional.extend(items);
                 }
+                ExpressionKind::NamedArgument(name, expr) => {
+                    named.push((name, self.eval_expression(expr)?));
+                }
+                _ => positional.push(self.eval_expression(arg)?),
+            }
+        }
+        match callee {
+            ObjectWrapper::FunctionObject(name, params, body, env_func, file) => {
+                if self.stack.len() >= MAX_CALL_DEPTH {
+                    return Err(Error::new(format!(
+                        "maximum call depth of {} exceeded",
+                        MAX_CALL_DEPTH
+                    ))
+                    .with_help("check that the recursion has a reachable base case"));
+                }
+                self.stack.push(Frame {
+                    name: name.as_deref().unwrap_or("<anonymous>").to_string(),
+                    span,
+                    file: self.file.as_deref().map(String::from),
+                });
+                // 参数个数这样的错误没有位置，回到调用方之后报告在调用表达式上
+                let caller = std::mem::replace(&mut self.file, file);
+                let ret = self.do_eval_function_call(
+                    name.as_deref(),
+                    &params,
+                    positional,
+                    named,
+                    &body,
+                    &env_func,
+                );
+                self.file = caller;
+                self.stack.pop();
+                ret
             }
-            Expression::FunctionExpression(params_ident, body) => {
-                self.do_eval_function_call(params_ident, &real_params, body, self.env.clone())
+            ObjectWrapper::BuiltinFn(nums, func) => {
+                if let Some((name, _)) = named.first() {
+                    return Err(format!(
+                        "builtin function does not accept named arguments, got `{}`",
+                        name.0
+                    )
+                    .into());
+                }
+                if nums != VARIADIC && positional.len() != nums {
+                    return Err(format!(
+                        "Wrong number of arguments, expect {} got {}",
+                        nums,
+                        positional.len()
+                    )
+                    .into());
+                }
+                func(positional)
+            }
+            obj => Err(match &func.kind {
+                ExpressionKind::Identifier(ident) => {
+                    format!("{} is not a function: {}", ident.0, obj.type_str())
+                }
+                _ => format!("{} is not a function", obj.type_str()),
             }
-            _ => Err("invalid call expression.".into()),
+            .into()),
         }
     }
 
+    // 位置参数依次绑定，多余的放入rest参数；命名参数按名字绑定；
+    // 没有传入的参数使用默认值，默认值在函数的作用域中求值，可以引用前面的参数
     fn do_eval_function_call(
         &mut self,
-        params_ident: &Vec<Ident>,
-        params: &Vec<ObjectWrapper>,
-        body: &Vec<Statement>,
-        mut env: Environment,
+        name: Option<&str>,
+        params: &[Parameter],
+        positional: Vec<ObjectWrapper>,
+        named: Vec<(&Ident, ObjectWrapper)>,
+        body: &[Statement],
+        closure: &Environment,
     ) -> Result<ObjectWrapper> {
-        if params.len() != params_ident.len() {
+        let signature = || {
+            format!(
+                "{}({})",
+                name.unwrap_or("<anonymous>"),
+                join_parameters(params)
+            )
+        };
+        let (rest, params) = match params.split_last() {
+            Some((last, init)) if last.rest => (Some(last), init),
+            _ => (None, params),
+        };
+
+        let total = positional.len();
+        let mut positional = positional.into_iter();
+        let mut values = params
+            .iter()
+            .map(|_| positional.next())
+            .collect::<Vec<Option<ObjectWrapper>>>();
+        let extra = positional.collect::<Vec<ObjectWrapper>>();
+        if !extra.is_empty() && rest.is_none() {
             return Err(format!(
-                "Invalid params, expect {} got {}",
-                params_ident.len(),
-                params.len()
+                "too many arguments for {}: expected at most {}, got {}",
+                signature(),
+                params.len(),
+                total
             )
             .into());
         }
-        params
-            .iter()
-            .zip(params_ident.iter())
-            .for_each(|(obj, param_ident)| {
-                env.set(&param_ident.0, obj.clone());
-            });
-        let mut evaluator = Evaluator::with_env(body, env);
-        evaluator.eval()
+
+        for (arg_name, value) in named {
+            match params.iter().position(|param| &param.name == arg_name) {
+                Some(i) if values[i].is_some() => {
+                    return Err(format!(
+                        "multiple values for argument `{}` of {}",
+                        arg_name.0,
+                        signature()
+                    )
+                    .into())
+                }
+                Some(i) => values[i] = Some(value),
+                None => {
+                    return Err(
+                        format!("unknown argument `{}` for {}", arg_name.0, signature()).into(),
+                    )
+                }
+            }
+        }
+
+        // 参数绑定在以闭包捕获的作用域为外层的新作用域中，不会影响定义处的同名变量
+        let env = Environment::new_enclosed(closure);
+        let outer = std::mem::replace(&mut self.env, env.clone());
+        let ret = self.bind_arguments(&env, params, values, rest, extra, &signature);
+        // 只留下self.env中的引用，返回时才能判断作用域是否还被闭包以外的值引用
+        drop(env);
+        // 函数体和调用方共用同一个Evaluator，出错时的调用栈才是完整的。
+        // 函数体中的错误属于定义函数的文件，即使它的位置是在eval_statements中才加上的
+        let ret = ret.and_then(|_| {
+            self.eval_statements(body)
+                .map_err(|e| e.or_file(self.file.as_deref()))
+        });
+        std::mem::replace(&mut self.env, outer).release();
+        ret
+    }
+
+    fn bind_arguments(
+        &mut self,
+        env: &Environment,
+        params: &[Parameter],
+        values: Vec<Option<ObjectWrapper>>,
+        rest: Option<&Parameter>,
+        extra: Vec<ObjectWrapper>,
+        signature: &dyn Fn() -> String,
+    ) -> Result<()> {
+        for (param, value) in params.iter().zip(values) {
+            let value = match (value, &param.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => self.eval_expression(default)?,
+                (None, None) => {
+                    return Err(
+                        format!("missing argument `{}` for {}", param.name.0, signature()).into(),
+                    )
+                }
+            };
+            env.set(&param.name.0, value);
+        }
+        if let Some(rest) = rest {
+            env.set(&rest.name.0, ObjectWrapper::Array(extra));
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,137 @@
+use crate::error::Result;
+use crate::eval::{format_float, quote, ObjectWrapper};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::iter::FromIterator;
+
+/// 可以作为Hash键的值。
+///
+/// 浮点数不能实现Eq/Hash，因此值为整数的浮点数（如`1.0`）会被当作对应的整数键，
+/// 与`1 == 1.0`的比较结果保持一致；其余的浮点数（包括NaN）不能作为键。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+}
+
+impl TryFrom<&ObjectWrapper> for HashKey {
+    type Error = crate::error::Error;
+
+    fn try_from(obj: &ObjectWrapper) -> Result<Self> {
+        match obj {
+            ObjectWrapper::Integer(v) => Ok(HashKey::Integer(*v)),
+            ObjectWrapper::Boolean(v) => Ok(HashKey::Boolean(*v)),
+            ObjectWrapper::String(v) => Ok(HashKey::String(v.clone())),
+            ObjectWrapper::Float(v)
+                if v.fract() == 0.0 && *v >= i64::MIN as f64 && *v < i64::MAX as f64 =>
+            {
+                Ok(HashKey::Integer(*v as i64))
+            }
+            ObjectWrapper::Float(v) => Err(format!(
+                "unusable as hash key: float {}, only integral floats are allowed",
+                format_float(*v)
+            )
+            .into()),
+            _ => Err(format!("unusable as hash key: {}", obj.type_str()).into()),
+        }
+    }
+}
+
+impl From<HashKey> for ObjectWrapper {
+    fn from(key: HashKey) -> Self {
+        match key {
+            HashKey::Integer(v) => ObjectWrapper::Integer(v),
+            HashKey::Boolean(v) => ObjectWrapper::Boolean(v),
+            HashKey::String(v) => ObjectWrapper::String(v),
+        }
+    }
+}
+
+impl Display for HashKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashKey::Integer(v) => write!(f, "{}", v),
+            HashKey::Boolean(v) => write!(f, "{}", v),
+            HashKey::String(v) => f.write_str(&quote(v)),
+        }
+    }
+}
+
+/// 保持插入顺序的Hash，keys/values以及输出的顺序都和插入顺序一致
+#[derive(Debug, Clone, Default)]
+pub struct HashObject {
+    entries: Vec<(HashKey, ObjectWrapper)>,
+    index: HashMap<HashKey, usize>,
+}
+
+impl HashObject {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &HashKey) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn get(&self, key: &HashKey) -> Option<&ObjectWrapper> {
+        self.index.get(key).map(|i| &self.entries[*i].1)
+    }
+
+    /// 已存在的键会保留原来的位置，只更新值
+    pub fn insert(&mut self, key: HashKey, value: ObjectWrapper) -> Option<ObjectWrapper> {
+        match self.index.get(&key) {
+            Some(i) => Some(std::mem::replace(&mut self.entries[*i].1, value)),
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &HashKey) -> Option<ObjectWrapper> {
+        let i = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for (_, pos) in self.index.iter_mut() {
+            if *pos > i {
+                *pos -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(HashKey, ObjectWrapper)> {
+        self.entries.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &HashKey> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &ObjectWrapper> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+}
+
+// 两个Hash包含相同的键值对即相等，与插入顺序无关
+impl PartialEq for HashObject {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl FromIterator<(HashKey, ObjectWrapper)> for HashObject {
+    fn from_iter<T: IntoIterator<Item = (HashKey, ObjectWrapper)>>(iter: T) -> Self {
+        let mut ret = HashObject::default();
+        for (k, v) in iter {
+            ret.insert(k, v);
+        }
+        ret
+    }
+}
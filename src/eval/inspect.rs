@@ -0,0 +1,93 @@
+use crate::eval::{quote, ObjectWrapper};
+
+/// inspect输出的长度限制，超出的部分用`...`代替
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InspectOptions {
+    /// 数组和Hash嵌套的最大层数，更深的部分输出为`[...]`或`{...}`
+    pub max_depth: usize,
+    /// 每个数组和Hash最多输出的元素个数
+    pub max_items: usize,
+    /// 字符串最多输出的字符个数
+    pub max_string: usize,
+}
+
+impl Default for InspectOptions {
+    fn default() -> Self {
+        InspectOptions {
+            max_depth: 6,
+            max_items: 100,
+            max_string: 1000,
+        }
+    }
+}
+
+impl ObjectWrapper {
+    /// 给人看的输出，用在REPL中。和Display一样是Monkey源码的形式，
+    /// 但过大、过深的值会被截断，因此结果不一定能被重新解析
+    pub fn inspect(&self) -> String {
+        self.inspect_with(&InspectOptions::default())
+    }
+
+    pub fn inspect_with(&self, options: &InspectOptions) -> String {
+        let mut ret = String::new();
+        inspect_into(self, options, 0, &mut ret);
+        ret
+    }
+}
+
+// 数组和Hash按值保存，函数也不展开闭包的环境，所以值中不会出现环；
+// 深度限制保证了很深的嵌套也只输出有限的内容
+fn inspect_into(obj: &ObjectWrapper, options: &InspectOptions, depth: usize, ret: &mut String) {
+    match obj {
+        ObjectWrapper::String(s) => {
+            let len = s.chars().count();
+            if len > options.max_string {
+                let head = s.chars().take(options.max_string).collect::<String>();
+                ret.push_str(&format!("{}... ({} chars)", quote(&head), len));
+            } else {
+                ret.push_str(&quote(s));
+            }
+        }
+        ObjectWrapper::Array(array) if depth >= options.max_depth && !array.is_empty() => {
+            ret.push_str("[...]")
+        }
+        ObjectWrapper::Array(array) => {
+            ret.push('[');
+            for (i, element) in array.iter().take(options.max_items).enumerate() {
+                if i > 0 {
+                    ret.push_str(", ");
+                }
+                inspect_into(element, options, depth + 1, ret);
+            }
+            push_more(array.len(), options.max_items, ret);
+            ret.push(']');
+        }
+        ObjectWrapper::Hash(hash) if depth >= options.max_depth && !hash.is_empty() => {
+            ret.push_str("{...}")
+        }
+        ObjectWrapper::Hash(hash) => {
+            ret.push('{');
+            for (i, (key, value)) in hash.iter().take(options.max_items).enumerate() {
+                if i > 0 {
+                    ret.push_str(", ");
+                }
+                inspect_into(&key.clone().into(), options, depth + 1, ret);
+                ret.push_str(": ");
+                inspect_into(value, options, depth + 1, ret);
+            }
+            push_more(hash.len(), options.max_items, ret);
+            ret.push('}');
+        }
+        ObjectWrapper::ReturnValue(v) => inspect_into(v, options, depth, ret),
+        obj => ret.push_str(&obj.to_string()),
+    }
+}
+
+fn push_more(len: usize, max_items: usize, ret: &mut String) {
+    if len > max_items {
+        if max_items > 0 {
+            ret.push_str(", ");
+        }
+        ret.push_str(&format!("... ({} more)", len - max_items));
+    }
+}
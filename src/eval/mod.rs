@@ -1,18 +1,29 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::fmt::{Display, Formatter};
 use std::sync::Arc;
 
+use crate::error::{Error, ErrorKind, Result};
 use crate::eval::environment::Environment;
-use crate::parser::Result;
+use crate::eval::hash::{HashKey, HashObject};
 
-use super::parser::program::{Ident, Statement};
+use super::parser::program::{join_parameters, Parameter, Statement};
 
 pub mod builtins;
 pub mod environment;
 pub mod evaluator;
+pub mod hash;
+pub mod inspect;
 
 #[cfg(test)]
 mod test;
 
+/// 执行Monkey代码的线程的栈大小。求值是递归的，每层函数调用要占用几十KB的栈(debug构建中更多)，
+/// 需要保证调用深度达到`evaluator::MAX_CALL_DEPTH`之前不会栈溢出
+pub const STACK_SIZE: usize = 256 << 20;
+
+// BuiltinFn比较的是函数指针，同一个内置函数总是来自Builtins中的同一个实例
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum ObjectWrapper {
     Null,
@@ -21,28 +32,61 @@ pub enum ObjectWrapper {
     Boolean(bool),
     String(String),
     Array(Vec<ObjectWrapper>),
+    Hash(HashObject),
+    Range(i64, i64),
     ReturnValue(Box<ObjectWrapper>),
-    ErrorObject(String),
-    FunctionObject(Arc<Vec<Ident>>, Arc<Vec<Statement>>, Environment),
+    // 循环中的break/continue，和ReturnValue一样沿着语句块向外传递
+    Break,
+    Continue,
+    // 被catch捕获的错误，message、kind、位置和经过的调用可以用下标读取，例如`e["message"]`
+    ErrorObject(Error),
+    // 名字来自函数声明或者绑定它的let/const，匿名函数没有名字；
+    // 最后是定义函数的文件，函数体中的错误报告在这个文件中的位置
+    FunctionObject(
+        Option<String>,
+        Arc<[Parameter]>,
+        Arc<[Statement]>,
+        Environment,
+        Option<Arc<str>>,
+    ),
     BuiltinFn(usize, BuiltinFunction),
 }
 
 pub type BuiltinFunction = fn(Vec<ObjectWrapper>) -> Result<ObjectWrapper>;
 
+/// 以Monkey源码的形式输出值，例如`5`、`"hi"`、`[1, 2]`、`{"a": 1}`、`<fn add(a, b)>`
 impl Display for ObjectWrapper {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            ObjectWrapper::Null => f.write_str("Null Object"),
-            ObjectWrapper::Integer(i) => write!(f, "Integer: {}", i),
-            ObjectWrapper::Float(flt) => write!(f, "Float: {}", flt),
-            ObjectWrapper::ReturnValue(v) => write!(f, "Retrun Object: {:?}", v),
-            ObjectWrapper::ErrorObject(err) => write!(f, "Error: {}", err),
-            ObjectWrapper::FunctionObject(idents, body, _) => {
-                write!(f, "FunctionObject: ident: {:?}, body: {:?}", idents, body)
+            ObjectWrapper::Null => f.write_str("null"),
+            ObjectWrapper::Integer(i) => write!(f, "{}", i),
+            ObjectWrapper::Float(flt) => f.write_str(&format_float(*flt)),
+            ObjectWrapper::Boolean(v) => write!(f, "{}", v),
+            ObjectWrapper::String(v) => f.write_str(&quote(v)),
+            ObjectWrapper::Array(array) => {
+                let elements = array
+                    .iter()
+                    .map(|obj| obj.to_string())
+                    .collect::<Vec<String>>();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            ObjectWrapper::Hash(hash) => {
+                let pairs = hash
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k, v))
+                    .collect::<Vec<String>>();
+                write!(f, "{{{}}}", pairs.join(", "))
             }
-            ObjectWrapper::String(v) => write!(f, "String: {}", v),
-            ObjectWrapper::Array(array) => write!(f, "Array: {:?}", array),
-            _ => f.write_str("unimplemented display objectWrapper"),
+            ObjectWrapper::Range(start, end) => write!(f, "{}..{}", start, end),
+            ObjectWrapper::ReturnValue(v) => write!(f, "{}", v),
+            ObjectWrapper::ErrorObject(err) => write!(f, "<{}: {}>", error_kind(err), err.info()),
+            ObjectWrapper::FunctionObject(name, params, ..) => match name {
+                Some(name) => write!(f, "<fn {}({})>", name, join_parameters(params)),
+                None => write!(f, "<fn({})>", join_parameters(params)),
+            },
+            ObjectWrapper::BuiltinFn(_, _) => f.write_str("<builtin fn>"),
+            ObjectWrapper::Break => f.write_str("break"),
+            ObjectWrapper::Continue => f.write_str("continue"),
         }
     }
 }
@@ -59,6 +103,101 @@ fn ensure_compare_with_same_type(one: &ObjectWrapper, two: &ObjectWrapper) -> Re
     Ok(())
 }
 
+/// 参与运算的两个数值，int和float混合时统一提升为float
+enum Numbers {
+    Int(i64, i64),
+    Float(f64, f64),
+}
+
+fn promote(one: &ObjectWrapper, two: &ObjectWrapper) -> Option<Numbers> {
+    match (one, two) {
+        (ObjectWrapper::Integer(one), ObjectWrapper::Integer(two)) => {
+            Some(Numbers::Int(*one, *two))
+        }
+        (ObjectWrapper::Integer(one), ObjectWrapper::Float(two)) => {
+            Some(Numbers::Float(*one as f64, *two))
+        }
+        (ObjectWrapper::Float(one), ObjectWrapper::Integer(two)) => {
+            Some(Numbers::Float(*one, *two as f64))
+        }
+        (ObjectWrapper::Float(one), ObjectWrapper::Float(two)) => Some(Numbers::Float(*one, *two)),
+        _ => None,
+    }
+}
+
+/// 浮点数的输出和字面量的写法一致：整数值保留小数点以便和整数区分，例如`1.0`，
+/// 很大和很小的数用指数形式，例如`1e20`、`1.5e-7`。
+/// 运算溢出得到的无穷大和NaN没有字面量，输出为`inf`、`-inf`和`nan`
+pub fn format_float(v: f64) -> String {
+    if v.is_nan() {
+        "nan".to_string()
+    } else if v.is_infinite() {
+        if v > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        // Debug输出是能精确读回的最短形式，在1e16以上和1e-4以下使用指数
+        format!("{:?}", v)
+    }
+}
+
+/// 脚本中看到的错误类型：`throw`抛出的是Error，解释器产生的是RuntimeError
+pub fn error_kind(err: &Error) -> &'static str {
+    match err.kind() {
+        ErrorKind::Thrown => "Error",
+        _ => "RuntimeError",
+    }
+}
+
+/// 错误对象可以用下标读取的字段
+pub const ERROR_FIELDS: &[&str] = &["message", "kind", "file", "line", "column", "stack"];
+
+fn error_field(err: &Error, field: &str) -> Result<ObjectWrapper> {
+    let ret = match field {
+        "message" => ObjectWrapper::String(err.info().to_string()),
+        "kind" => ObjectWrapper::String(error_kind(err).to_string()),
+        "file" => err.file().map_or(ObjectWrapper::Null, |file| {
+            ObjectWrapper::String(file.to_string())
+        }),
+        "line" => err.span().map_or(ObjectWrapper::Null, |span| {
+            ObjectWrapper::Integer(span.start.line as i64)
+        }),
+        "column" => err.span().map_or(ObjectWrapper::Null, |span| {
+            ObjectWrapper::Integer(span.start.column as i64)
+        }),
+        // 经过的每一层调用，最内层在前，例如`["inner at 3:5", "outer at 7:1"]`
+        "stack" => ObjectWrapper::Array(
+            err.frames()
+                .iter()
+                .map(|frame| ObjectWrapper::String(frame.to_string()))
+                .collect(),
+        ),
+        _ => {
+            return Err(Error::new(format!("error has no field `{}`", field))
+                .with_help(format!("available fields: {}", ERROR_FIELDS.join(", "))))
+        }
+    };
+    Ok(ret)
+}
+
+/// 带引号的字符串字面量，转义方式和Lexer一致，输出的结果可以被重新解析
+pub fn quote(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\t' => ret.push_str("\\t"),
+            '\r' => ret.push_str("\\r"),
+            '\0' => ret.push_str("\\0"),
+            ch if ch.is_control() => ret.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+            ch => ret.push(ch),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
 impl ObjectWrapper {
     pub fn type_str(&self) -> &str {
         match self {
@@ -67,100 +206,152 @@ impl ObjectWrapper {
             ObjectWrapper::Float(_) => "float",
             ObjectWrapper::Boolean(_) => "bool",
             ObjectWrapper::String(_) => "string",
+            ObjectWrapper::Range(_, _) => "range",
             ObjectWrapper::ReturnValue(_) => "return_value",
+            ObjectWrapper::Break => "break",
+            ObjectWrapper::Continue => "continue",
             ObjectWrapper::ErrorObject(_) => "error",
-            ObjectWrapper::FunctionObject(_, _, _) => "function",
+            ObjectWrapper::FunctionObject(..) => "function",
             ObjectWrapper::BuiltinFn(_, _) => "builtin-fn",
             ObjectWrapper::Array(_) => "array",
+            ObjectWrapper::Hash(_) => "hash",
             // _ => "untyped",
         }
     }
 
     pub fn add(&self, other: &Self) -> Result<Self> {
-        match self {
-            ObjectWrapper::Integer(one) => match other {
-                ObjectWrapper::Integer(two) => Ok(ObjectWrapper::Integer(one + two)),
-                ObjectWrapper::Float(two) => Ok(ObjectWrapper::Float((*one as f64) + two)),
-                _ => Err(format!("int cannot '+' with type {}.", other.type_str()).into()),
-            },
-            ObjectWrapper::String(one) => match other {
+        if let ObjectWrapper::String(one) = self {
+            return match other {
                 ObjectWrapper::String(two) => Ok(ObjectWrapper::String(format!("{}{}", one, two))),
                 ObjectWrapper::Integer(two) => Ok(ObjectWrapper::String(format!("{}{}", one, two))),
-                ObjectWrapper::Float(two) => Ok(ObjectWrapper::String(format!("{}{}", one, two))),
+                ObjectWrapper::Float(two) => Ok(ObjectWrapper::String(format!(
+                    "{}{}",
+                    one,
+                    format_float(*two)
+                ))),
                 ObjectWrapper::Boolean(two) => Ok(ObjectWrapper::String(format!("{}{}", one, two))),
                 _ => Err(format!("string cannot '+' with type {}.", other.type_str()).into()),
-            },
-            _ => Err(format!("type {} dose not support '+' operation.", self.type_str()).into()),
+            };
         }
+
+        self.arithmetic(other, "+", i64::checked_add, |one, two| one + two)
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self> {
+        self.arithmetic(other, "-", i64::checked_sub, |one, two| one - two)
     }
 
     pub fn eq(&self, other: &Self) -> Result<Self> {
+        if let Some(numbers) = promote(self, other) {
+            return Ok(ObjectWrapper::Boolean(match numbers {
+                Numbers::Int(one, two) => one == two,
+                Numbers::Float(one, two) => one == two,
+            }));
+        }
+        // 任何值都可以和null比较
+        if let ObjectWrapper::Null = self {
+            return Ok(ObjectWrapper::Boolean(other == &ObjectWrapper::Null));
+        }
+        if let ObjectWrapper::Null = other {
+            return Ok(ObjectWrapper::Boolean(false));
+        }
         ensure_compare_with_same_type(self, other)?;
 
         match (self, other) {
-            (ObjectWrapper::Integer(one), ObjectWrapper::Integer(two)) => {
-                Ok(ObjectWrapper::Boolean(one == two))
-            }
-            (ObjectWrapper::Float(one), ObjectWrapper::Float(two)) => {
+            (ObjectWrapper::Boolean(one), ObjectWrapper::Boolean(two)) => {
                 Ok(ObjectWrapper::Boolean(one == two))
             }
-            (ObjectWrapper::Boolean(one), ObjectWrapper::Boolean(two)) => {
+            (ObjectWrapper::String(one), ObjectWrapper::String(two)) => {
                 Ok(ObjectWrapper::Boolean(one == two))
             }
-            (ObjectWrapper::Null, ObjectWrapper::Null) => Ok(ObjectWrapper::Boolean(true)),
             _ => Ok(ObjectWrapper::Boolean(false)),
         }
     }
 
     pub fn not_eq(&self, other: &Self) -> Result<Self> {
-        ensure_compare_with_same_type(self, other)?;
-
-        match (self, other) {
-            (ObjectWrapper::Integer(one), ObjectWrapper::Integer(two)) => {
-                Ok(ObjectWrapper::Boolean(one != two))
-            }
-            (ObjectWrapper::Float(one), ObjectWrapper::Float(two)) => {
-                Ok(ObjectWrapper::Boolean(one != two))
-            }
-            (ObjectWrapper::Boolean(one), ObjectWrapper::Boolean(two)) => {
-                Ok(ObjectWrapper::Boolean(one != two))
-            }
-            (ObjectWrapper::Null, ObjectWrapper::Null) => Ok(ObjectWrapper::Boolean(false)),
-            _ => Ok(ObjectWrapper::Boolean(true)),
+        match self.eq(other)? {
+            ObjectWrapper::Boolean(v) => Ok(ObjectWrapper::Boolean(!v)),
+            _ => unreachable!(),
         }
     }
 
     pub fn multi(&self, other: &Self) -> Result<Self> {
-        ensure_compare_with_same_type(self, other)?;
+        self.arithmetic(other, "*", i64::checked_mul, |one, two| one * two)
+    }
 
-        match (self, other) {
-            (ObjectWrapper::Integer(one), ObjectWrapper::Integer(two)) => {
-                Ok(ObjectWrapper::Integer(one * two))
-            }
-            (ObjectWrapper::Float(one), ObjectWrapper::Float(two)) => {
-                Ok(ObjectWrapper::Float(one * two))
+    /// int和int相除结果仍是int，向0取整
+    pub fn divide(&self, other: &Self) -> Result<Self> {
+        self.ensure_non_zero_divisor(other)?;
+        self.arithmetic(other, "/", i64::checked_div, |one, two| one / two)
+    }
+
+    /// 向下取整的除法，结果的类型遵循同样的提升规则
+    pub fn floor_divide(&self, other: &Self) -> Result<Self> {
+        self.ensure_non_zero_divisor(other)?;
+        self.arithmetic(
+            other,
+            "~/",
+            |one, two| {
+                let ret = one.checked_div(two)?;
+                if one % two != 0 && (one < 0) != (two < 0) {
+                    Some(ret - 1)
+                } else {
+                    Some(ret)
+                }
+            },
+            |one, two| (one / two).floor(),
+        )
+    }
+
+    /// 取余，结果的符号和被除数相同，与'/'保持一致
+    pub fn modulo(&self, other: &Self) -> Result<Self> {
+        self.ensure_non_zero_divisor(other)?;
+        self.arithmetic(other, "%", i64::checked_rem, |one, two| one % two)
+    }
+
+    /// int的负数次幂结果为float，其余情况遵循同样的提升规则
+    pub fn power(&self, other: &Self) -> Result<Self> {
+        if let (ObjectWrapper::Integer(one), ObjectWrapper::Integer(two)) = (self, other) {
+            if *two < 0 {
+                return Ok(ObjectWrapper::Float((*one as f64).powf(*two as f64)));
             }
-            _ => Err(format!(
-                "'*' is not support between {} and {}",
-                self.type_str(),
-                other.type_str()
-            )
-            .into()),
         }
+        self.arithmetic(
+            other,
+            "**",
+            |one, two| one.checked_pow(u32::try_from(two).ok()?),
+            f64::powf,
+        )
     }
 
-    pub fn divide(&self, other: &Self) -> Result<Self> {
-        ensure_compare_with_same_type(self, other)?;
+    pub fn negate(&self) -> Result<Self> {
+        match self {
+            ObjectWrapper::Integer(v) => v
+                .checked_neg()
+                .map(ObjectWrapper::Integer)
+                .ok_or_else(|| format!("integer overflow: -({})", v).into()),
+            ObjectWrapper::Float(v) => Ok(ObjectWrapper::Float(-v)),
+            _ => Err(format!("cannot eval {} after '-'.", self.type_str()).into()),
+        }
+    }
 
-        match (self, other) {
-            (ObjectWrapper::Integer(one), ObjectWrapper::Integer(two)) => {
-                Ok(ObjectWrapper::Integer(one / two))
-            }
-            (ObjectWrapper::Float(one), ObjectWrapper::Float(two)) => {
-                Ok(ObjectWrapper::Float(one / two))
-            }
-            _ => Err(format!(
-                "'/' is not support between {} and {}",
+    /// 所有数值运算统一的类型提升规则：int与int运算得到int，有float参与时得到float；
+    /// int运算溢出时报错而不是panic
+    fn arithmetic(
+        &self,
+        other: &Self,
+        operator: &str,
+        int_op: fn(i64, i64) -> Option<i64>,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Result<Self> {
+        match promote(self, other) {
+            Some(Numbers::Int(one, two)) => int_op(one, two)
+                .map(ObjectWrapper::Integer)
+                .ok_or_else(|| format!("integer overflow: {} {} {}", one, operator, two).into()),
+            Some(Numbers::Float(one, two)) => Ok(ObjectWrapper::Float(float_op(one, two))),
+            None => Err(format!(
+                "'{}' is not support between {} and {}",
+                operator,
                 self.type_str(),
                 other.type_str()
             )
@@ -168,18 +359,61 @@ impl ObjectWrapper {
         }
     }
 
+    fn ensure_non_zero_divisor(&self, other: &Self) -> Result<()> {
+        let is_zero = match other {
+            ObjectWrapper::Integer(v) => *v == 0,
+            ObjectWrapper::Float(v) => *v == 0.0,
+            _ => false,
+        };
+        if is_zero && promote(self, other).is_some() {
+            return Err("division by zero".into());
+        }
+        Ok(())
+    }
+
     pub fn great_than(&self, other: &Self) -> Result<Self> {
-        ensure_compare_with_same_type(self, other)?;
+        self.compare(other, ">", |ordering| ordering == Ordering::Greater)
+    }
 
-        match (self, other) {
-            (ObjectWrapper::Integer(one), ObjectWrapper::Integer(two)) => {
-                Ok(ObjectWrapper::Boolean(one > two))
+    pub fn less_than(&self, other: &Self) -> Result<Self> {
+        self.compare(other, "<", |ordering| ordering == Ordering::Less)
+    }
+
+    pub fn great_equal(&self, other: &Self) -> Result<Self> {
+        self.compare(other, ">=", |ordering| ordering != Ordering::Less)
+    }
+
+    pub fn less_equal(&self, other: &Self) -> Result<Self> {
+        self.compare(other, "<=", |ordering| ordering != Ordering::Greater)
+    }
+
+    /// 数值按提升规则比较，字符串按字典序比较；和NaN比较的结果总是false
+    fn compare(&self, other: &Self, operator: &str, check: fn(Ordering) -> bool) -> Result<Self> {
+        let ordering = match (promote(self, other), self, other) {
+            (Some(Numbers::Int(one, two)), _, _) => Some(one.cmp(&two)),
+            (Some(Numbers::Float(one, two)), _, _) => one.partial_cmp(&two),
+            (None, ObjectWrapper::String(one), ObjectWrapper::String(two)) => Some(one.cmp(two)),
+            _ => {
+                return Err(format!(
+                    "'{}' is not support between {} and {}",
+                    operator,
+                    self.type_str(),
+                    other.type_str()
+                )
+                .into())
             }
-            (ObjectWrapper::Float(one), ObjectWrapper::Float(two)) => {
-                Ok(ObjectWrapper::Boolean(one > two))
+        };
+        Ok(ObjectWrapper::Boolean(ordering.is_some_and(check)))
+    }
+
+    /// `start..end`，不包含end
+    pub fn range(&self, other: &Self) -> Result<Self> {
+        match (self, other) {
+            (ObjectWrapper::Integer(start), ObjectWrapper::Integer(end)) => {
+                Ok(ObjectWrapper::Range(*start, *end))
             }
             _ => Err(format!(
-                "'>' is not support between {} and {}",
+                "'..' is not support between {} and {}",
                 self.type_str(),
                 other.type_str()
             )
@@ -187,20 +421,56 @@ impl ObjectWrapper {
         }
     }
 
-    pub fn less_than(&self, other: &Self) -> Result<Self> {
-        ensure_compare_with_same_type(self, other)?;
+    /// for循环依次取出的值：数组的元素、字符串的字符、Hash的键、range中的整数
+    pub fn iterate(&self) -> Result<Box<dyn Iterator<Item = ObjectWrapper>>> {
+        match self {
+            ObjectWrapper::Array(array) => Ok(Box::new(array.clone().into_iter())),
+            ObjectWrapper::String(v) => Ok(Box::new(
+                v.chars()
+                    .map(|c| ObjectWrapper::String(c.to_string()))
+                    .collect::<Vec<ObjectWrapper>>()
+                    .into_iter(),
+            )),
+            ObjectWrapper::Hash(hash) => Ok(Box::new(
+                hash.keys()
+                    .cloned()
+                    .map(ObjectWrapper::from)
+                    .collect::<Vec<ObjectWrapper>>()
+                    .into_iter(),
+            )),
+            ObjectWrapper::Range(start, end) => {
+                Ok(Box::new((*start..*end).map(ObjectWrapper::Integer)))
+            }
+            _ => Err(format!("{} is not iterable", self.type_str()).into()),
+        }
+    }
 
-        match (self, other) {
-            (ObjectWrapper::Integer(one), ObjectWrapper::Integer(two)) => {
-                Ok(ObjectWrapper::Boolean(one > two))
+    /// 返回把index处的值替换为value之后的新对象，用于`arr[i] = v`和`map[k] = v`
+    pub fn set_index(&self, index: &Self, value: Self) -> Result<Self> {
+        match (self, index) {
+            (ObjectWrapper::Array(array), ObjectWrapper::Integer(i)) => {
+                if *i < 0 || *i as usize >= array.len() {
+                    return Err(format!(
+                        "Index out of range. expect [0, {}), got {}",
+                        array.len(),
+                        i
+                    )
+                    .into());
+                }
+                let mut array = array.clone();
+                array[*i as usize] = value;
+                Ok(ObjectWrapper::Array(array))
             }
-            (ObjectWrapper::Float(one), ObjectWrapper::Float(two)) => {
-                Ok(ObjectWrapper::Boolean(one < two))
+            (ObjectWrapper::Hash(hash), key) => {
+                let key = HashKey::try_from(key)?;
+                let mut hash = hash.clone();
+                hash.insert(key, value);
+                Ok(ObjectWrapper::Hash(hash))
             }
             _ => Err(format!(
-                "'<' is not support between {} and {}",
+                "index assignment is not supported for type {} with index type: {}",
                 self.type_str(),
-                other.type_str()
+                index.type_str()
             )
             .into()),
         }
@@ -209,8 +479,7 @@ impl ObjectWrapper {
     pub fn index(&self, other: &Self) -> Result<Self> {
         match (self, other) {
             (ObjectWrapper::Array(array), ObjectWrapper::Integer(index)) => {
-                let index = *index as usize;
-                if index >= array.len() {
+                if *index < 0 || *index as usize >= array.len() {
                     Err(format!(
                         "Index out of range. expect [0, {}), got {}",
                         array.len(),
@@ -218,9 +487,16 @@ impl ObjectWrapper {
                     )
                     .into())
                 } else {
-                    Ok(array[index].clone())
+                    Ok(array[*index as usize].clone())
                 }
             }
+            (ObjectWrapper::Hash(hash), key) => {
+                let key = HashKey::try_from(key)?;
+                Ok(hash.get(&key).cloned().unwrap_or(ObjectWrapper::Null))
+            }
+            (ObjectWrapper::ErrorObject(err), ObjectWrapper::String(field)) => {
+                error_field(err, field)
+            }
             _ => Err(format!(
                 "index operation is not supported for type {} with index type: {}",
                 self.type_str(),
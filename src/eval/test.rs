@@ -1,18 +1,37 @@
-use crate::eval::evaluator::Evaluator;
+use crate::error::{ErrorKind, Result};
+use crate::eval::environment::Environment;
+use crate::eval::evaluator::{Evaluator, MAX_CALL_DEPTH};
+use crate::eval::inspect::InspectOptions;
 use crate::eval::ObjectWrapper;
 use crate::lexer::lexer::Lexer;
-use crate::parser::Parser;
-use crate::parser::Result;
+use crate::parser::{diagnostic, Parser};
+use std::sync::Arc;
 
 #[cfg(test)]
 fn test_eval(input: &str) -> Result<ObjectWrapper> {
     let l = Lexer::new(input);
     let mut p = Parser::new(l);
     let program = p.parse_program()?;
-    let mut evaluator = Evaluator::new(&program.statements);
+    let mut evaluator = Evaluator::with_env(&program.statements, Environment::default());
     evaluator.eval()
 }
 
+// 没有被捕获的运行时错误，带有出错的位置
+fn eval_error(input: &str) -> String {
+    match test_eval(input) {
+        Ok(obj) => panic!("{} evaluates to {:?}, expected an error", input, obj),
+        Err(e) => e.to_string(),
+    }
+}
+
+// 值的Debug输出，没有被捕获的错误输出为`Err(...)`
+fn eval_debug(input: &str) -> String {
+    match test_eval(input) {
+        Ok(obj) => format!("{:?}", obj),
+        Err(e) => format!("Err({})", e),
+    }
+}
+
 #[test]
 fn test_integer_object() {
     let cases = vec![("5", 5), ("10", 10)];
@@ -21,11 +40,137 @@ fn test_integer_object() {
         if let ObjectWrapper::Integer(v) = obj {
             assert_eq!(v, expect);
         } else {
-            assert!(false, "{:?} is not an integer object.", obj);
+            panic!("{:?} is not an integer object.", obj);
         }
     }
 }
 
+#[test]
+fn test_float_object() {
+    let cases = [
+        ("1.5", ObjectWrapper::Float(1.5)),
+        ("-2.5", ObjectWrapper::Float(-2.5)),
+        ("1.5e-3", ObjectWrapper::Float(0.0015)),
+        ("1.5 + 1", ObjectWrapper::Float(2.5)),
+        ("1 + 1.5", ObjectWrapper::Float(2.5)),
+        ("2 * 1.5", ObjectWrapper::Float(3.0)),
+        ("1.5 * 2", ObjectWrapper::Float(3.0)),
+        ("3.0 * 2.0", ObjectWrapper::Float(6.0)),
+        ("1 == 1.0", ObjectWrapper::Boolean(true)),
+        ("1.5 != 1", ObjectWrapper::Boolean(true)),
+        ("2 > 1.5", ObjectWrapper::Boolean(true)),
+        ("1.5 < 1", ObjectWrapper::Boolean(false)),
+        ("\"v\" + 2.0", ObjectWrapper::String("v2.0".to_string())),
+        (
+            "let x = 0.5; if (x < 1) { x * 4 } else { 0 }",
+            ObjectWrapper::Float(2.0),
+        ),
+    ];
+
+    for (input, expect) in cases {
+        let obj = test_eval(input).unwrap();
+        assert_eq!(obj, expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_arithmetic_operators() {
+    let cases = [
+        ("10 - 3", ObjectWrapper::Integer(7)),
+        ("10 - 3.5", ObjectWrapper::Float(6.5)),
+        ("7 / 2", ObjectWrapper::Integer(3)),
+        ("-7 / 2", ObjectWrapper::Integer(-3)),
+        ("7 / 2.0", ObjectWrapper::Float(3.5)),
+        ("7 ~/ 2", ObjectWrapper::Integer(3)),
+        ("-7 ~/ 2", ObjectWrapper::Integer(-4)),
+        ("7.5 ~/ 2", ObjectWrapper::Float(3.0)),
+        ("7 % 3", ObjectWrapper::Integer(1)),
+        ("-7 % 3", ObjectWrapper::Integer(-1)),
+        ("7.5 % 2", ObjectWrapper::Float(1.5)),
+        ("2 ** 10", ObjectWrapper::Integer(1024)),
+        ("2 ** 3 ** 2", ObjectWrapper::Integer(512)),
+        ("2 ** -1", ObjectWrapper::Float(0.5)),
+        ("4 ** 0.5", ObjectWrapper::Float(2.0)),
+        ("-2 ** 2", ObjectWrapper::Integer(4)),
+        ("2 * 3 ** 2", ObjectWrapper::Integer(18)),
+        ("1 + 2 * 3 - 4 / 2", ObjectWrapper::Integer(5)),
+    ];
+
+    for (input, expect) in cases {
+        let obj = test_eval(input).unwrap();
+        assert_eq!(obj, expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_arithmetic_errors() {
+    let cases = [
+        ("1 / 0", "1:1: division by zero"),
+        ("1.5 / 0", "1:1: division by zero"),
+        ("1 % 0", "1:1: division by zero"),
+        ("1 ~/ 0.0", "1:1: division by zero"),
+        (
+            "9223372036854775807 + 1",
+            "1:1: integer overflow: 9223372036854775807 + 1",
+        ),
+        (
+            "let x = -9223372036854775807 - 1; x / -1",
+            "1:35: integer overflow: -9223372036854775808 / -1",
+        ),
+        ("2 ** 64", "1:1: integer overflow: 2 ** 64"),
+        ("true - 1", "1:1: '-' is not support between bool and int"),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(eval_error(input), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_comparison_and_logical_operators() {
+    let cases = [
+        ("1 <= 1", ObjectWrapper::Boolean(true)),
+        ("2 <= 1", ObjectWrapper::Boolean(false)),
+        ("1 >= 1.5", ObjectWrapper::Boolean(false)),
+        ("\"abc\" < \"abd\"", ObjectWrapper::Boolean(true)),
+        ("\"abc\" == \"abc\"", ObjectWrapper::Boolean(true)),
+        ("1 == null", ObjectWrapper::Boolean(false)),
+        ("null == null", ObjectWrapper::Boolean(true)),
+        ("true && false", ObjectWrapper::Boolean(false)),
+        ("true || false", ObjectWrapper::Boolean(true)),
+        ("1 < 2 && 2 < 3 || false", ObjectWrapper::Boolean(true)),
+        ("false || 1 >= 2 && true", ObjectWrapper::Boolean(false)),
+        // 短路求值：右边没有被执行，因此不会报错
+        ("false && undefined", ObjectWrapper::Boolean(false)),
+        ("true || 1 / 0 == 1", ObjectWrapper::Boolean(true)),
+        (
+            "let x = null; x != null && x > 0",
+            ObjectWrapper::Boolean(false),
+        ),
+        (
+            "let x = 5; x != null && x > 0",
+            ObjectWrapper::Boolean(true),
+        ),
+        ("true || \"a\" >= 1", ObjectWrapper::Boolean(true)),
+    ];
+
+    for (input, expect) in cases {
+        let obj = test_eval(input).unwrap();
+        assert_eq!(obj, expect, "{}", input);
+    }
+
+    let errors = [
+        ("1 && true", "1:1: '&&' expects bool operands, got int"),
+        (
+            "false || \"a\" >= 1",
+            "1:10: '>=' is not support between string and int",
+        ),
+    ];
+    for (input, expect) in errors {
+        assert_eq!(eval_error(input), expect, "{}", input);
+    }
+}
+
 #[test]
 fn test_return_statement() {
     let cases = [
@@ -47,16 +192,53 @@ fn test_return_statement() {
 }
 
 #[test]
-fn test_error_handle() {
-    let cases = [("foobar", "identifier not found: foobar")];
+fn test_if_else_expression() {
+    let cases = [
+        ("if (true) { 10 }", ObjectWrapper::Integer(10)),
+        ("if (false) { 10 }", ObjectWrapper::Null),
+        ("if (1 < 2) { 10 } else { 20 }", ObjectWrapper::Integer(10)),
+        ("if (1 > 2) { 10 } else { 20 }", ObjectWrapper::Integer(20)),
+        (
+            "let x = 5; if (x > 10) { 1 } else if (x > 3) { 2 } else { 3 }",
+            ObjectWrapper::Integer(2),
+        ),
+        (
+            "let x = 1; if (x > 10) { 1 } else if (x > 3) { 2 } else { 3 }",
+            ObjectWrapper::Integer(3),
+        ),
+        (
+            "let f = fn(x) { if (x > 1) { return 1; } else { return 2; } 3 }; f(0)",
+            ObjectWrapper::Integer(2),
+        ),
+    ];
 
     for (input, expect) in cases {
         let obj = test_eval(input).unwrap();
-        if let ObjectWrapper::ErrorObject(v) = obj {
-            assert_eq!(&v, expect);
-        } else {
-            assert!(false, "{:?} is not an error object.", obj);
-        }
+        assert_eq!(obj, expect);
+    }
+}
+
+#[test]
+fn test_error_handle() {
+    let cases = [
+        ("foobar", "1:1: identifier not found: foobar"),
+        (
+            "let a = 1;\nlet b = a + foobar;",
+            "2:13: identifier not found: foobar",
+        ),
+        (
+            "let f = fn(x) {\n  x + y\n};\nf(1);",
+            "2:7: identifier not found: y",
+        ),
+        (
+            "let x = [1, 2]; x[-1]",
+            "1:17: Index out of range. expect [0, 2), got -1",
+        ),
+        ("[1, 2][2]", "1:1: Index out of range. expect [0, 2), got 2"),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(eval_error(input), expect, "{}", input);
     }
 }
 
@@ -84,7 +266,7 @@ fn test_function_call() {
         if let ObjectWrapper::Integer(v) = obj {
             assert_eq!(v, expect);
         } else {
-            assert!(false, "expect integer: {}, got {:?}", expect, obj);
+            panic!("expect integer: {}, got {:?}", expect, obj);
         }
     }
 }
@@ -93,18 +275,851 @@ fn test_function_call() {
 fn test_builtin_functions() {
     let cases = [
         ("len(\"hello\");", ObjectWrapper::Integer(5)),
+        ("len(\"héllo😀\");", ObjectWrapper::Integer(6)),
+        ("byte_len(\"héllo😀\");", ObjectWrapper::Integer(10)),
+        ("puts()", ObjectWrapper::Null),
+        ("puts(\"a\", 1, [2])", ObjectWrapper::Null),
+    ];
+
+    for (input, expect) in cases {
+        let obj = test_eval(input).unwrap();
+        assert_eq!(obj, expect);
+    }
+
+    let errors = [
         (
-            "len(10);",
-            ObjectWrapper::ErrorObject("Argument to `len` not supported, got int".to_string()),
+            "byte_len([1]);",
+            "1:1: Argument to `byte_len` must be STRING, got array",
         ),
+        ("len(10);", "1:1: Argument to `len` not supported, got int"),
         (
             "len(10, 10);",
-            ObjectWrapper::ErrorObject("Wrong number of arguments, expect 1 got 2".to_string()),
+            "1:1: Wrong number of arguments, expect 1 got 2",
         ),
     ];
+    for (input, expect) in errors {
+        assert_eq!(eval_error(input), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_error_with_file() {
+    let l = Lexer::with_file("let x = 1;\n  -true;", "main.mk");
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+    let mut evaluator = Evaluator::with_env(&program.statements, Environment::default());
+    evaluator.set_file("main.mk");
+    assert_eq!(
+        evaluator.eval().unwrap_err().to_string(),
+        "main.mk:2:3: cannot eval bool after '-'."
+    );
+}
+
+#[test]
+fn test_render_runtime_error() {
+    let input = "let total = 1;\nlet x = totl * 2;";
+    let l = Lexer::new(input);
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+    let mut evaluator = Evaluator::with_env(&program.statements, Environment::default());
+    let err = evaluator.eval().unwrap_err();
+
+    assert_eq!(
+        diagnostic::render(&err, input),
+        "error: identifier not found: totl
+ --> 2:9
+  |
+2 | let x = totl * 2;
+  |         ^^^^
+  = help: did you mean `total`?
+"
+    );
+}
+
+#[test]
+fn test_hash_object() {
+    let cases = [
+        (
+            "let h = {\"a\": 1, 2: \"b\", true: 3.5}; h[\"a\"]",
+            ObjectWrapper::Integer(1),
+        ),
+        (
+            "let h = {\"a\": 1, 2: \"b\"}; h[2]",
+            ObjectWrapper::String("b".to_string()),
+        ),
+        ("{true: 3.5}[1 < 2]", ObjectWrapper::Float(3.5)),
+        (
+            "{1: \"one\"}[1.0]",
+            ObjectWrapper::String("one".to_string()),
+        ),
+        ("{\"a\": 1}[\"b\"]", ObjectWrapper::Null),
+        (
+            "let k = \"x\"; {k + \"y\": 5}[\"xy\"]",
+            ObjectWrapper::Integer(5),
+        ),
+        ("{1: 1, 1.0: 2}[1]", ObjectWrapper::Integer(2)),
+        ("len({1: 1, 2: 2})", ObjectWrapper::Integer(2)),
+    ];
 
     for (input, expect) in cases {
         let obj = test_eval(input).unwrap();
-        assert_eq!(obj, expect);
+        assert_eq!(obj, expect, "{}", input);
+    }
+
+    let errors = [
+        (
+            "{1.5: 1}",
+            "1:2: unusable as hash key: float 1.5, only integral floats are allowed",
+        ),
+        ("{\"a\": 1}[[1]]", "1:1: unusable as hash key: array"),
+    ];
+    for (input, expect) in errors {
+        assert_eq!(eval_error(input), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_hash_builtins() {
+    let cases = [
+        ("keys({\"b\": 1, \"a\": 2})", "[String(\"b\"), String(\"a\")]"),
+        ("values({\"b\": 1, \"a\": 2})", "[Integer(1), Integer(2)]"),
+        ("has_key({\"b\": 1}, \"b\")", "Boolean(true)"),
+        ("has_key({\"b\": 1}, \"a\")", "Boolean(false)"),
+        (
+            "let h = {1: 1, 2: 2, 3: 3}; let d = delete(h, 2); [keys(d), len(h)]",
+            "[Array([Integer(1), Integer(3)]), Integer(3)]",
+        ),
+        (
+            "let m = merge({1: 1, 2: 2}, {2: 20, 3: 30}); [keys(m), values(m)]",
+            "[Array([Integer(1), Integer(2), Integer(3)]), Array([Integer(1), Integer(20), Integer(30)])]",
+        ),
+        (
+            "keys([1])",
+            "Err(1:1: Argument to 'keys' must be HASH, got \"array\")",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        let got = match test_eval(input) {
+            Ok(ObjectWrapper::Array(array)) => format!("{:?}", array),
+            _ => eval_debug(input),
+        };
+        assert_eq!(got, expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_lexical_scope() {
+    let cases = [
+        // 闭包能看到定义之后才加入的绑定
+        (
+            "let f = fn() { later }; let later = 5; f()",
+            ObjectWrapper::Integer(5),
+        ),
+        // 递归
+        (
+            "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(5)",
+            ObjectWrapper::Integer(120),
+        ),
+        // 闭包捕获的是定义处的作用域
+        (
+            "let adder = fn(x) { fn(y) { x + y } }; let addTwo = adder(2); let x = 10; addTwo(3)",
+            ObjectWrapper::Integer(5),
+        ),
+        // 参数和函数内的let不会影响外层的同名变量
+        (
+            "let x = 1; let f = fn(x) { let y = x; y }; f(2); x",
+            ObjectWrapper::Integer(1),
+        ),
+        (
+            "let x = 1; let f = fn() { let x = 2; x }; f() + x",
+            ObjectWrapper::Integer(3),
+        ),
+        // 块语句中的let只在块内可见
+        (
+            "let x = 1; if (true) { let x = 2; x } else { 0 }",
+            ObjectWrapper::Integer(2),
+        ),
+        (
+            "let x = 1; if (true) { let x = 2; }; x",
+            ObjectWrapper::Integer(1),
+        ),
+    ];
+
+    for (input, expect) in cases {
+        let obj = test_eval(input).unwrap();
+        assert_eq!(obj, expect, "{}", input);
+    }
+
+    let errors = [(
+        "if (true) { let y = 2; }; y",
+        "1:27: identifier not found: y",
+    )];
+    for (input, expect) in errors {
+        assert_eq!(eval_error(input), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_call_any_callee() {
+    let cases = [
+        (
+            "let fns = [fn(x) { x + 1 }, fn(x) { x * 2 }]; fns[1](5)",
+            ObjectWrapper::Integer(10),
+        ),
+        (
+            "let makeAdder = fn(x) { fn(y) { x + y } }; makeAdder(1)(2)",
+            ObjectWrapper::Integer(3),
+        ),
+        (
+            "let obj = {\"handler\": fn(e) { e * 3 }}; obj[\"handler\"](4)",
+            ObjectWrapper::Integer(12),
+        ),
+        ("fn(x) { x }(7)", ObjectWrapper::Integer(7)),
+        ("[len][0]([1, 2])", ObjectWrapper::Integer(2)),
+        (
+            "let curry = fn(f) { fn(a) { fn(b) { f(a, b) } } }; curry(fn(a, b) { a - b })(5)(3)",
+            ObjectWrapper::Integer(2),
+        ),
+    ];
+
+    for (input, expect) in cases {
+        let obj = test_eval(input).unwrap();
+        assert_eq!(obj, expect, "{}", input);
+    }
+
+    let errors = [
+        ("let x = 1; x(2)", "1:12: x is not a function: int"),
+        ("[1][0](2)", "1:1: int is not a function"),
+    ];
+    for (input, expect) in errors {
+        assert_eq!(eval_error(input), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_loop() {
+    let cases = [
+        (
+            "let f = fn(arr) { for (x in arr) { if (x > 1) { return x } } null }; f([1, 5, 7])",
+            "Integer(5)",
+        ),
+        (
+            "let f = fn() { for (c in \"héllo\") { if (c != \"h\") { return c } } }; f()",
+            "String(\"é\")",
+        ),
+        (
+            "let f = fn() { for (k in {\"b\": 1, \"a\": 2}) { return k } }; f()",
+            "String(\"b\")",
+        ),
+        (
+            "let f = fn() { for (i in 3..10) { if (i * i > 20) { return i } } }; f()",
+            "Integer(5)",
+        ),
+        (
+            "let f = fn() { for (x in [1, 2, 3]) { if (x < 3) { continue } return x } }; f()",
+            "Integer(3)",
+        ),
+        (
+            "let f = fn() { for (x in [1, 2]) { if (true) { break } return x } 0 }; f()",
+            "Integer(0)",
+        ),
+        (
+            "let f = fn() { while (true) { return 1 } }; f()",
+            "Integer(1)",
+        ),
+        ("while (true) { break }", "Null"),
+        ("while (false) { 1 }", "Null"),
+        ("for (i in 0..3) { i }", "Null"),
+        ("0..3", "Range(0, 3)"),
+        (
+            "for (x in [1]) { let y = x }; y",
+            "Err(1:31: identifier not found: y)",
+        ),
+        (
+            "for (x in [1]) { }; x",
+            "Err(1:21: identifier not found: x)",
+        ),
+        ("break", "Err(1:1: 'break' outside of a loop)"),
+        (
+            "let f = fn() { continue }; for (x in [1]) { f() }",
+            "Err(1:16: 'continue' outside of a loop)",
+        ),
+        ("for (x in 5) { x }", "Err(1:11: int is not iterable)"),
+        ("while (1) { 1 }", "Err(1:8: Invalid 'while' condition.)"),
+        (
+            "1.5..3",
+            "Err(1:1: '..' is not support between float and int)",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(eval_debug(input), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_assignment() {
+    let cases = [
+        ("let x = 1; x = 2; x", "Integer(2)"),
+        ("let x = 1; x = 5", "Integer(5)"),
+        ("let a = 1; let b = 2; a = b = 3; a + b", "Integer(6)"),
+        (
+            "let i = 0; let sum = 0; while (i < 5) { i += 1; sum += i; } sum",
+            "Integer(15)",
+        ),
+        ("let x = 10; x -= 3; x *= 2; x /= 7; x", "Integer(2)"),
+        ("let s = \"a\"; s += \"b\"; s", "String(\"ab\")"),
+        (
+            "let r = {}; for (x in 0..3) { r[x] = x * x }; values(r)",
+            "Array([Integer(0), Integer(1), Integer(4)])",
+        ),
+        // 修改的是定义所在作用域中的绑定
+        (
+            "let count = 0; let inc = fn() { count += 1 }; inc(); inc(); count",
+            "Integer(2)",
+        ),
+        (
+            "let x = 1; if (true) { x = 2; let x = 3; x = 4; }; x",
+            "Integer(2)",
+        ),
+        (
+            "let counter = fn() { let n = 0; fn() { n += 1 } }; let c = counter(); c(); c()",
+            "Integer(2)",
+        ),
+        (
+            "let arr = [1, 2, 3]; arr[1] = 20; arr",
+            "Array([Integer(1), Integer(20), Integer(3)])",
+        ),
+        ("let arr = [1, 2, 3]; arr[2] += 1", "Integer(4)"),
+        (
+            "let m = {\"k\": 1}; m[\"k\"] = 2; m[\"j\"] = 3; values(m)",
+            "Array([Integer(2), Integer(3)])",
+        ),
+        (
+            "let m = {\"a\": [1, [2]]}; m[\"a\"][1][0] = 9; m[\"a\"]",
+            "Array([Integer(1), Array([Integer(9)])])",
+        ),
+        // 有副作用的下标只求值一次
+        (
+            "let arr = [0, 0, 0]; let i = 0; let next = fn() { i += 1; i - 1 }; arr[next()] += 5; [arr, i]",
+            "Array([Array([Integer(5), Integer(0), Integer(0)]), Integer(1)])",
+        ),
+        (
+            "let m = [[0, 0], [0, 0]]; let i = 0; let next = fn() { i += 1; i - 1 }; m[next()][1] = 7; [m, i]",
+            "Array([Array([Array([Integer(0), Integer(7)]), Array([Integer(0), Integer(0)])]), Integer(1)])",
+        ),
+        ("y = 1", "Err(1:1: assignment to undeclared variable: y)"),
+        (
+            "let arr = [1]; arr[1] = 2",
+            "Err(1:16: Index out of range. expect [0, 1), got 1)",
+        ),
+        (
+            "let x = 1; x[0] = 2",
+            "Err(1:12: index assignment is not supported for type int with index type: int)",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(eval_debug(input), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_const() {
+    let cases = [
+        ("const x = 1; x + 1", "Integer(2)"),
+        ("const x = 1; if (true) { let x = 2; x }", "Integer(2)"),
+        ("const x = 1; if (true) { const x = 2; }; x", "Integer(1)"),
+        // 函数中的赋值在调用时才能发现目标是const
+        (
+            "let f = fn() { limit = 2 }; const limit = 1; f()",
+            "Err(1:16: cannot assign to constant: limit)",
+        ),
+        (
+            "let f = fn() { let limit = 3; limit = 2 }; const limit = 1; f()",
+            "Integer(2)",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(eval_debug(input), expect, "{}", input);
     }
+
+    // 解析时无法发现的重复声明，例如REPL中分多次输入的代码
+    let env = Environment::default();
+    for (input, expect) in [
+        ("const x = 1;", "Null"),
+        ("let x = 2;", "Err(1:1: cannot redeclare constant: x)"),
+        ("x = 3;", "Err(1:1: cannot assign to constant: x)"),
+        ("x", "Integer(1)"),
+    ] {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let mut evaluator = Evaluator::with_env(&program.statements, env.clone());
+        let got = match evaluator.eval() {
+            Ok(obj) => format!("{:?}", obj),
+            Err(e) => format!("Err({})", e),
+        };
+        assert_eq!(got, expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_function_declaration() {
+    let cases = [
+        (
+            "fn fact(n) { if (n < 2) { 1 } else { n * fact(n - 1) } } fact(5)",
+            "Integer(120)",
+        ),
+        // 提升：声明之前就可以调用，顶层函数之间可以相互递归
+        (
+            "let r = isEven(10); fn isEven(n) { if (n == 0) { true } else { isOdd(n - 1) } } fn isOdd(n) { if (n == 0) { false } else { isEven(n - 1) } } r",
+            "Boolean(true)",
+        ),
+        (
+            "fn outer() { return inner(); fn inner() { 42 } } outer()",
+            "Integer(42)",
+        ),
+        ("if (true) { fn f() { 1 } }; f", "Err(1:29: identifier not found: f)"),
+        (
+            "const f = 1; if (true) { fn f() { 2 } f() }",
+            "Integer(2)",
+        ),
+        (
+            "fn f(a) { a } f(1, 2)",
+            "Err(1:15: too many arguments for f(a): expected at most 1, got 2)",
+        ),
+        (
+            "let g = fn(a) { a }; g()",
+            "Err(1:22: missing argument `a` for g(a))",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(eval_debug(input), expect, "{}", input);
+    }
+
+    let cases = [
+        ("fn add(a, b) { a + b } add", "<fn add(a, b)>"),
+        ("let id = fn(x) { x }; id", "<fn id(x)>"),
+        ("fn(x) { x }", "<fn(x)>"),
+    ];
+    for (input, expect) in cases {
+        let obj = test_eval(input).unwrap();
+        assert_eq!(obj.to_string(), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_call_arguments() {
+    let cases = [
+        ("fn f(a, b = 2) { a * 10 + b } f(1)", "Integer(12)"),
+        ("fn f(a, b = 2) { a * 10 + b } f(1, 3)", "Integer(13)"),
+        ("fn f(a, b = a + 1) { b } f(5)", "Integer(6)"),
+        (
+            "fn f(a, b = 2, c = 3) { [a, b, c] } f(1, c: 30)",
+            "Array([Integer(1), Integer(2), Integer(30)])",
+        ),
+        ("fn f(a, b) { a - b } f(b: 1, a: 5)", "Integer(4)"),
+        (
+            "fn f(a, ...rest) { [a, rest] } f(1)",
+            "Array([Integer(1), Array([])])",
+        ),
+        (
+            "fn f(a, ...rest) { rest } f(1, 2, 3)",
+            "Array([Integer(2), Integer(3)])",
+        ),
+        (
+            "fn f(a, b, c) { a + b + c } let xs = [2, 3]; f(1, ...xs)",
+            "Integer(6)",
+        ),
+        (
+            "fn f(...xs) { len(xs) } f(...0..4, ...\"ab\")",
+            "Integer(6)",
+        ),
+        ("len(...[[1, 2]])", "Integer(2)"),
+        (
+            "fn f(a, b = 2, ...rest) { a } f()",
+            "Err(1:31: missing argument `a` for f(a, b = 2, ...rest))",
+        ),
+        (
+            "fn f(a) { a } f(1, 2, 3)",
+            "Err(1:15: too many arguments for f(a): expected at most 1, got 3)",
+        ),
+        (
+            "fn f(a) { a } f(b: 1)",
+            "Err(1:15: unknown argument `b` for f(a))",
+        ),
+        (
+            "fn f(a) { a } f(1, a: 2)",
+            "Err(1:15: multiple values for argument `a` of f(a))",
+        ),
+        ("fn f(a) { a } f(...1)", "Err(1:20: int is not iterable)"),
+        (
+            "len(x: [1])",
+            "Err(1:1: builtin function does not accept named arguments, got `x`)",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(eval_debug(input), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_template_literal() {
+    let cases = [
+        (
+            "let name = \"Monkey\"; let n = 3; `Hello ${name}, you have ${n * 2} items`",
+            "Hello Monkey, you have 6 items",
+        ),
+        (
+            "`${1.0} ${true} ${null} ${[1, \"a\"]} ${{\"k\": 2}}`",
+            "1.0 true null [1, \"a\"] {\"k\": 2}",
+        ),
+        ("fn add(a, b) { a + b } `${add}`", "<fn add(a, b)>"),
+        ("let x = 1; `${`${x + 1}`}`", "2"),
+        ("``", ""),
+    ];
+
+    for (input, expect) in cases {
+        let obj = test_eval(input).unwrap();
+        assert_eq!(obj, ObjectWrapper::String(expect.to_string()), "{}", input);
+    }
+
+    assert_eq!(
+        eval_error("`a ${missing}`"),
+        "1:6: identifier not found: missing"
+    );
+}
+
+#[test]
+fn test_comments_and_identifiers() {
+    let input = "#!/usr/bin/env monkey
+// 玩家的血量
+let player_hp = 100; /* 初始值 */
+let item2 = 20; // 道具加成
+/* 
+ * 计算总和
+ */
+player_hp + item2";
+    assert_eq!(test_eval(input).unwrap(), ObjectWrapper::Integer(120));
+}
+
+#[test]
+fn test_display_object() {
+    let cases = vec![
+        ("5", "5"),
+        ("2.0", "2.0"),
+        ("1e20", "1e20"),
+        ("100000.0 * 100000.0 * 100000.0 * 100000.0", "1e20"),
+        ("1.5e-7", "1.5e-7"),
+        // 无穷大和NaN没有字面量
+        ("1e308 * 10", "inf"),
+        ("-1e308 * 10", "-inf"),
+        ("1e308 * 10 - 1e308 * 10", "nan"),
+        ("true", "true"),
+        ("if (false) { 1 }", "null"),
+        ("\"hi\"", "\"hi\""),
+        ("\"a\\\"b\\\\c\\n\\u{7}\"", "\"a\\\"b\\\\c\\n\\u{7}\""),
+        ("[1, \"a\", [2.5]]", "[1, \"a\", [2.5]]"),
+        ("{\"a\": 1, 2: [true]}", "{\"a\": 1, 2: [true]}"),
+        ("1..3", "1..3"),
+        ("fn add(a, b) { a + b } add", "<fn add(a, b)>"),
+        ("fn(x) { x }", "<fn(x)>"),
+        ("len", "<builtin fn>"),
+    ];
+    for (input, expect) in cases {
+        assert_eq!(test_eval(input).unwrap().to_string(), expect, "{}", input);
+    }
+
+    // 输出的字符串和数组可以被重新解析成相同的值
+    let value = test_eval("[\"tab\\there\", {\"k\\\"\": \"\\u{0}\"}]").unwrap();
+    assert_eq!(test_eval(&value.to_string()).unwrap(), value);
+    let value = test_eval("[1e20, 0.1, 1.0, -0.0, 1.5e-7, 123456789.125]").unwrap();
+    assert_eq!(test_eval(&value.to_string()).unwrap(), value);
+}
+
+#[test]
+fn test_inspect_object() {
+    let value = test_eval("[1, \"a\", {\"k\": [2]}]").unwrap();
+    assert_eq!(value.inspect(), value.to_string());
+
+    let options = InspectOptions {
+        max_depth: 2,
+        max_items: 3,
+        max_string: 5,
+    };
+    let cases = vec![
+        ("[1, 2, 3]", "[1, 2, 3]"),
+        ("[1, 2, 3, 4, 5]", "[1, 2, 3, ... (2 more)]"),
+        (
+            "{1: 1, 2: 2, 3: 3, 4: 4}",
+            "{1: 1, 2: 2, 3: 3, ... (1 more)}",
+        ),
+        ("\"hello\"", "\"hello\""),
+        ("\"hello world\"", "\"hello\"... (11 chars)"),
+        ("[[[1]], [[]], [{}]]", "[[[...]], [[]], [{}]]"),
+        ("[{\"a\": {\"b\": 1}}]", "[{\"a\": {...}}]"),
+    ];
+    for (input, expect) in cases {
+        let obj = test_eval(input).unwrap();
+        assert_eq!(obj.inspect_with(&options), expect, "{}", input);
+    }
+
+    // 很深的嵌套也只输出有限的内容
+    let mut deep = ObjectWrapper::Array(vec![]);
+    for _ in 0..1000 {
+        deep = ObjectWrapper::Array(vec![deep]);
+    }
+    let text = deep.inspect();
+    assert!(text.starts_with("[[[[[[[...]]]]]]"), "{}", text);
+}
+
+#[test]
+fn test_try_catch() {
+    let cases = [
+        ("try { 1 } catch (e) { 2 }", "Integer(1)"),
+        ("try { throw \"bad\" } catch (e) { e[\"message\"] }", "String(\"bad\")"),
+        ("try { throw \"bad\" } catch (e) { e[\"kind\"] }", "String(\"Error\")"),
+        ("try { 1 / 0 } catch (e) { [e[\"kind\"], e[\"message\"]] }", "Array([String(\"RuntimeError\"), String(\"division by zero\")])"),
+        (
+            "try {\n  missing\n} catch (e) { [e[\"line\"], e[\"column\"], e[\"file\"]] }",
+            "Array([Integer(2), Integer(3), Null])",
+        ),
+        // 经过的每一层调用，最内层在前
+        (
+            "fn inner() { throw \"x\" }\nlet outer = fn() { inner() };\ntry { outer() } catch (e) { e[\"stack\"] }",
+            "Array([String(\"inner at 2:20\"), String(\"outer at 3:7\")])",
+        ),
+        (
+            "try { fn() { throw \"x\" }() } catch (e) { e[\"stack\"] }",
+            "Array([String(\"<anonymous> at 1:7\")])",
+        ),
+        // 重新抛出的错误保留原来的位置
+        (
+            "try { try { throw \"x\" } catch (e) { throw e } } catch (e) { e[\"column\"] }",
+            "Integer(13)",
+        ),
+        ("try { throw \"x\" } catch (e) { e }", "ErrorObject(1:7: x)"),
+        ("let e = 1; try { throw \"x\" } catch (e) { }; e", "Integer(1)"),
+        // finally总会执行
+        (
+            "let log = \"\"; try { log += \"a\" } finally { log += \"b\" }; log",
+            "String(\"ab\")",
+        ),
+        (
+            "try { throw \"x\" } finally { missing }",
+            "Err(1:29: identifier not found: missing)",
+        ),
+        (
+            "let n = 0; try { n += 1; throw \"x\" } catch (e) { n += 10 } finally { n += 100 }; n",
+            "Integer(111)",
+        ),
+        (
+            "let n = 0; let f = fn() { try { return 1 } finally { n = 5 } }; [f(), n]",
+            "Array([Integer(1), Integer(5)])",
+        ),
+        (
+            "let f = fn() { try { throw \"x\" } finally { return 2 } }; f()",
+            "Integer(2)",
+        ),
+        (
+            "let n = 0; for (i in 0..5) { try { if (i == 3) { break } } finally { n += 1 } }; n",
+            "Integer(4)",
+        ),
+        ("try { throw \"x\" } finally { 1 }", "Err(1:7: x)"),
+        ("throw 1", "Err(1:7: throw expects a string or an error, got int)"),
+        (
+            "try { throw \"x\" } catch (e) { e[\"name\"] }",
+            "Err(1:31: error has no field `name`)",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(eval_debug(input), expect, "{}", input);
+    }
+
+    // 没有被捕获的错误是Err，捕获之后作为结果的错误是Ok中的值
+    let err = test_eval("fn f() { throw \"bad\" } f()").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Thrown);
+    assert_eq!(err.info(), "bad");
+    assert_eq!(err.frames().len(), 1);
+    let obj = test_eval("try { throw \"bad\" } catch (e) { e }").unwrap();
+    assert_eq!(obj.type_str(), "error");
+    assert_eq!(obj.to_string(), "<Error: bad>");
+}
+
+#[test]
+fn test_call_stack() {
+    let cases = [
+        // 在函数中捕获的错误也有完整的调用栈
+        (
+            "fn f() { try { g() } catch (e) { e[\"stack\"] } } fn g() { throw \"x\" } let h = fn() { f() }; h()",
+            "Array([String(\"g at 1:16\"), String(\"f at 1:85\"), String(\"h at 1:92\")])",
+        ),
+        // 默认值在被调用的函数中求值，参数个数的错误属于调用方
+        (
+            "fn f(a = missing) { a } try { f() } catch (e) { e[\"stack\"] }",
+            "Array([String(\"f at 1:31\")])",
+        ),
+        (
+            "fn f(a) { a } try { f() } catch (e) { e[\"stack\"] }",
+            "Array([])",
+        ),
+        (
+            "fn f(n) { if (n == 0) { throw \"done\" } f(n - 1) } try { f(3) } catch (e) { len(e[\"stack\"]) }",
+            "Integer(4)",
+        ),
+        // 出错之后调用栈恢复，后面的调用不受影响
+        (
+            "fn f() { throw \"x\" } try { f() } catch (e) { } try { throw \"y\" } catch (e) { e[\"stack\"] }",
+            "Array([])",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(eval_debug(input), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_render_keyword_help() {
+    let input = "lett x = 5;";
+    let err = test_eval(input).unwrap_err();
+
+    assert_eq!(
+        diagnostic::render(&err, input),
+        "error: identifier not found: lett
+ --> 1:1
+  |
+1 | lett x = 5;
+  | ^^^^
+  = help: did you mean `let`?
+"
+    );
+    assert_eq!(
+        test_eval("let form = 1\nform\nform").unwrap(),
+        ObjectWrapper::Integer(1)
+    );
+}
+
+#[test]
+fn test_render_traceback() {
+    let input = "fn count(n) {\n    if (n == 0) { return nope; }\n    count(n - 1)\n}\nlet start = fn() { count(3) };\nstart()";
+    let program = Parser::new(Lexer::with_file(input, "main.mk"))
+        .parse_program()
+        .unwrap();
+    let mut evaluator = Evaluator::with_env(&program.statements, Environment::default());
+    evaluator.set_file("main.mk");
+    let err = evaluator.eval().unwrap_err();
+
+    assert_eq!(
+        diagnostic::render(&err, input),
+        "error: identifier not found: nope
+ --> main.mk:2:26
+  |
+2 |     if (n == 0) { return nope; }
+  |                          ^^^^
+traceback (most recent call first):
+  0: count at main.mk:3:5
+     ... repeated 2 more times
+  3: count at main.mk:5:20
+  4: start at main.mk:6:1
+"
+    );
+
+    // 不在函数中的错误没有调用栈
+    let err = test_eval("nope").unwrap_err();
+    assert!(err.frames().is_empty());
+    assert_eq!(diagnostic::traceback(&err), "");
+}
+
+#[test]
+fn test_max_call_depth() {
+    // 和main一样在足够大的栈上运行，测试线程默认的栈达不到调用深度的限制
+    let handle = std::thread::Builder::new()
+        .stack_size(crate::eval::STACK_SIZE)
+        .spawn(|| {
+            let deep = eval_debug("fn f(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(900)");
+            let caught = eval_debug(
+                "fn f(n) { f(n + 1) } try { f(0) } catch (e) { [e[\"kind\"], len(e[\"stack\"])] }",
+            );
+            let err = test_eval("fn f(n) { f(n + 1) }\nf(0)").unwrap_err();
+            (deep, caught, err)
+        })
+        .unwrap();
+    let (deep, caught, err) = handle.join().unwrap();
+
+    assert_eq!(deep, "Integer(900)");
+    assert_eq!(caught, "Array([String(\"RuntimeError\"), Integer(1000)])");
+    assert_eq!(err.to_string(), "1:11: maximum call depth of 1000 exceeded");
+    assert_eq!(err.frames().len(), MAX_CALL_DEPTH);
+    assert!(diagnostic::traceback(&err).contains("     ... repeated 998 more times"));
+}
+
+#[test]
+fn test_release_closure_scope() {
+    // 块和函数调用中创建的闭包没有逃逸时，它们和作用域之间的引用环在离开作用域时被打破，
+    // 否则留下的作用域会一直持有外层作用域的引用
+    let input = "let total = 0;
+for (i in 0..100) {
+    let g = fn(x) { x + i };
+    fn helper() { g(1) }
+    if (true) { let h = fn() { g }; total += h()(0) }
+    total += helper();
+}
+let make = fn() { let n = 1; fn get() { n } get() };
+total += make();";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    let env = Environment::default();
+    let mut evaluator = Evaluator::with_env(&program.statements, env.clone());
+    evaluator.eval().unwrap();
+    drop(evaluator);
+    assert_eq!(env.get("total"), Some(ObjectWrapper::Integer(10001)));
+    // 剩下的是env自己和make捕获的引用
+    assert_eq!(env.ref_count(), 2);
+
+    // 逃逸到外面的闭包仍然能使用它捕获的作用域
+    let cases = [
+        (
+            "let fs = {}; for (i in 0..3) { let j = i * 10; fs[i] = fn() { j } }; fs[2]()",
+            "Integer(20)",
+        ),
+        (
+            "let counter = fn() { let n = 0; let inc = fn() { n += 1 }; [inc] }; let c = counter()[0]; c(); c()",
+            "Integer(2)",
+        ),
+        (
+            "let f = null; if (true) { let x = 5; if (true) { f = fn() { x } } }; f()",
+            "Integer(5)",
+        ),
+    ];
+    for (input, expect) in cases {
+        assert_eq!(eval_debug(input), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_function_body_shared() {
+    // 每次执行函数声明和函数字面量得到的函数共用语法树中的函数体
+    let input =
+        "fn outer() { fn helper() { 1 } let anon = fn() { 2 }; [helper, anon] } [outer(), outer()]";
+    let value = test_eval(input).unwrap();
+    let bodies = match &value {
+        ObjectWrapper::Array(calls) => calls
+            .iter()
+            .flat_map(|call| match call {
+                ObjectWrapper::Array(fns) => fns.clone(),
+                obj => panic!("{:?} is not an array", obj),
+            })
+            .map(|func| match func {
+                ObjectWrapper::FunctionObject(_, _, body, _, _) => body,
+                obj => panic!("{:?} is not a function", obj),
+            })
+            .collect::<Vec<_>>(),
+        obj => panic!("{:?} is not an array", obj),
+    };
+    assert!(Arc::ptr_eq(&bodies[0], &bodies[2]));
+    assert!(Arc::ptr_eq(&bodies[1], &bodies[3]));
 }
@@ -1,99 +1,317 @@
-use crate::lexer::token::Token;
+use crate::lexer::span::{Position, Span};
+use crate::lexer::token::{Comment, SpannedToken, TemplatePart, Token};
+use std::sync::Arc;
 
 pub struct Lexer {
-    input: String,
+    input: Arc<str>,
+    // 输入在end处结束，模板字符串中`${...}`的Lexer只读取源码中的一段
+    end: usize,
+    file: Option<String>,
     position: usize,
     read_position: usize,
-    ch: u8,
+    ch: char,
+    // 当前字符ch所在的行列
+    line: usize,
+    column: usize,
+    // 已经跳过的注释
+    comments: Vec<Comment>,
 }
 
 impl Lexer {
     pub fn new<T: Into<String>>(input: T) -> Lexer {
+        let input: Arc<str> = Arc::from(input.into());
         let mut ret = Lexer {
-            input: input.into(),
+            end: input.len(),
+            input,
+            file: None,
             position: 0,
             read_position: 0,
-            ch: 0,
+            ch: '\0',
+            line: 1,
+            column: 0,
+            comments: vec![],
         };
         ret.read_char();
+        // 第一行的`#!`是给shell用的，整行跳过
+        if ret.ch == '#' && ret.peek_char() == '!' {
+            let start = ret.current_position();
+            ret.skip_line_comment();
+            ret.push_comment(start);
+        }
         ret
     }
 
+    /// 创建一个带有文件名的Lexer，文件名会出现在错误信息中
+    pub fn with_file<T: Into<String>, F: Into<String>>(input: T, file: F) -> Lexer {
+        let mut ret = Lexer::new(input);
+        ret.file = Some(file.into());
+        ret
+    }
+
+    /// 读取span范围内源码的Lexer，产生的位置仍然是相对于整个源码的，
+    /// 用于解析模板字符串中`${...}`里的表达式
+    pub fn sub_lexer(&self, span: Span) -> Lexer {
+        let mut ret = Lexer {
+            input: self.input.clone(),
+            end: span.end.offset,
+            file: self.file.clone(),
+            position: span.start.offset,
+            read_position: span.start.offset,
+            ch: '\0',
+            line: span.start.line,
+            column: span.start.column - 1,
+            comments: vec![],
+        };
+        ret.read_char();
+        ret
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// 取出到目前为止跳过的注释，按在源码中出现的顺序排列
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
+    }
+
+    /// 按字符读取，position和read_position是字节偏移，column按字符计数
     pub fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            self.ch = 0;
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 1;
         } else {
-            self.ch = self.input.as_bytes()[self.read_position];
+            self.column += 1;
         }
+
         self.position = self.read_position;
-        self.read_position += 1;
+        match self.input[std::cmp::min(self.read_position, self.end)..self.end]
+            .chars()
+            .next()
+        {
+            Some(ch) => {
+                self.ch = ch;
+                self.read_position += ch.len_utf8();
+            }
+            None => {
+                self.ch = '\0';
+                self.read_position += 1;
+            }
+        }
+    }
+
+    fn current_position(&self) -> Position {
+        Position::new(
+            std::cmp::min(self.position, self.end),
+            self.line,
+            self.column,
+        )
     }
 
     pub fn read_identifier(&mut self) -> String {
         let pos = self.position;
-        while (self.ch as char).is_ascii_alphabetic() {
+        while is_identifier_char(self.ch) {
             self.read_char();
         }
-        String::from_utf8(self.input.as_bytes()[pos..self.position].to_vec()).unwrap()
+        self.input[pos..self.position].to_string()
+    }
+
+    pub fn next_token(&mut self) -> SpannedToken {
+        loop {
+            self.skip_whitespace();
+            let start = self.current_position();
+            let token = match (self.ch, self.peek_char()) {
+                ('/', '/') => {
+                    self.skip_line_comment();
+                    self.push_comment(start);
+                    continue;
+                }
+                ('/', '*') => {
+                    if self.skip_block_comment() {
+                        self.push_comment(start);
+                        continue;
+                    }
+                    Token::Illegal("unterminated block comment".to_string())
+                }
+                _ => self.read_token(),
+            };
+            return SpannedToken {
+                token,
+                span: Span::new(start, self.current_position()),
+            };
+        }
     }
 
-    pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+    fn push_comment(&mut self, start: Position) {
+        let end = self.current_position();
+        self.comments.push(Comment {
+            text: self.input[start.offset..end.offset].to_string(),
+            span: Span::new(start, end),
+        });
+    }
+
+    // 跳过到行尾，换行符留给skip_whitespace
+    fn skip_line_comment(&mut self) {
+        while self.ch != '\n' && !self.at_end() {
+            self.read_char();
+        }
+    }
+
+    // 块注释可以嵌套，例如`/* a /* b */ c */`；没有结束时返回false
+    fn skip_block_comment(&mut self) -> bool {
+        let mut depth = 0;
+        loop {
+            match (self.ch, self.peek_char()) {
+                _ if self.at_end() => return false,
+                ('/', '*') => {
+                    depth += 1;
+                    self.read_char();
+                }
+                ('*', '/') => {
+                    depth -= 1;
+                    self.read_char();
+                    if depth == 0 {
+                        self.read_char();
+                        return true;
+                    }
+                }
+                _ => {}
+            }
+            self.read_char();
+        }
+    }
+
+    fn read_token(&mut self) -> Token {
         let ret = match self.ch {
-            b'=' => {
-                if self.peek_char() == b'=' {
+            '=' => {
+                if self.peek_char() == '=' {
                     self.read_char();
                     Token::Eq
                 } else {
                     Token::Assign
                 }
             }
-            b'+' => Token::Plus,
-            b'-' => Token::Minus,
-            b'!' => {
-                if self.peek_char() == b'=' {
+            '+' => self.with_assign(Token::Plus, Token::PlusAssign),
+            '-' => self.with_assign(Token::Minus, Token::MinusAssign),
+            '!' => {
+                if self.peek_char() == '=' {
                     self.read_char();
                     Token::NotEq
                 } else {
                     Token::Bang
                 }
             }
-            b'*' => Token::Asterisk,
-            b'/' => Token::Slash,
-            b'<' => Token::LT,
-            b'>' => Token::GT,
-            b',' => Token::Comma,
-            b';' => Token::Semicolon,
-            b':' => Token::Colon,
+            '*' => {
+                if self.peek_char() == '*' {
+                    self.read_char();
+                    Token::DoubleAsterisk
+                } else {
+                    self.with_assign(Token::Asterisk, Token::AsteriskAssign)
+                }
+            }
+            '/' => self.with_assign(Token::Slash, Token::SlashAssign),
+            '%' => Token::Percent,
+            '~' => {
+                if self.peek_char() == '/' {
+                    self.read_char();
+                    Token::TildeSlash
+                } else {
+                    self.unexpected_char()
+                }
+            }
+            '<' => {
+                if self.peek_char() == '=' {
+                    self.read_char();
+                    Token::LTE
+                } else {
+                    Token::LT
+                }
+            }
+            '>' => {
+                if self.peek_char() == '=' {
+                    self.read_char();
+                    Token::GTE
+                } else {
+                    Token::GT
+                }
+            }
+            '&' => {
+                if self.peek_char() == '&' {
+                    self.read_char();
+                    Token::And
+                } else {
+                    self.unexpected_char()
+                }
+            }
+            '|' => {
+                if self.peek_char() == '|' {
+                    self.read_char();
+                    Token::Or
+                } else {
+                    self.unexpected_char()
+                }
+            }
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            ':' => Token::Colon,
+            '.' => {
+                if self.peek_char() == '.' && self.peek_char_at(1) == '.' {
+                    self.read_char();
+                    self.read_char();
+                    Token::Ellipsis
+                } else if self.peek_char() == '.' {
+                    self.read_char();
+                    Token::DotDot
+                } else {
+                    self.unexpected_char()
+                }
+            }
 
-            b'(' => Token::LParen,
-            b')' => Token::RParen,
-            b'[' => Token::LBracket,
-            b']' => Token::RBracket,
-            b'{' => Token::LBrace,
-            b'}' => Token::RBrace,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '{' => Token::LBrace,
+            '}' => Token::RBrace,
 
-            b'"' => self.read_string(),
-            0 => Token::EOF,
+            '"' => self.read_string(),
+            '`' => self.read_template(),
+            '\0' if self.at_end() => Token::EOF,
             _ => {
-                let ch = self.ch as char;
-
                 // read_identifier和read_number_token中都进行了read_char，所以直接returns
-                if ch.is_ascii_alphabetic() {
+                if is_identifier_start(self.ch) {
                     return Token::from_str(self.read_identifier());
-                } else if ch.is_numeric() {
+                } else if self.ch.is_ascii_digit() {
                     return self.read_number_token();
                 } else {
-                    Token::Illegal
+                    self.unexpected_char()
                 }
             }
         };
         self.read_char();
-        return ret;
+        ret
+    }
+
+    fn at_end(&self) -> bool {
+        self.position >= self.end
+    }
+
+    fn unexpected_char(&self) -> Token {
+        Token::Illegal(format!("unexpected character `{}`", self.ch.escape_debug()))
+    }
+
+    // 后面跟着'='时是复合赋值运算符，例如'+='
+    fn with_assign(&mut self, token: Token, assign: Token) -> Token {
+        if self.peek_char() == '=' {
+            self.read_char();
+            assign
+        } else {
+            token
+        }
     }
 
     fn skip_whitespace(&mut self) {
-        while (self.ch as char).is_whitespace() {
+        while self.ch.is_whitespace() {
             self.read_char();
         }
     }
@@ -101,47 +319,212 @@ impl Lexer {
     fn read_number_token(&mut self) -> Token {
         let pos = self.position;
         let mut is_float = false;
-        loop {
-            let ch = self.ch as char;
+        self.read_digits();
 
-            if ch.is_numeric() || (!is_float && ch == '.') {
+        // 小数部分，'.'后面必须跟着数字
+        if self.ch == '.' && self.peek_char().is_ascii_digit() {
+            is_float = true;
+            self.read_char();
+            self.read_digits();
+        }
+
+        // 指数部分，例如1e10、1.5e-3、2E+5
+        if self.ch == 'e' || self.ch == 'E' {
+            let next = self.peek_char();
+            let sign = next == '+' || next == '-';
+            if next.is_ascii_digit() || (sign && self.peek_char_at(1).is_ascii_digit()) {
+                is_float = true;
                 self.read_char();
-                if ch == '.' {
-                    is_float = true;
+                if sign {
+                    self.read_char();
                 }
-            } else {
-                break;
+                self.read_digits();
             }
         }
 
-        let sub_str =
-            String::from_utf8(self.input.as_bytes()[pos..self.position].to_vec()).unwrap();
+        let sub_str = &self.input[pos..self.position];
         if is_float {
-            Token::from_float(sub_str.parse::<f64>().unwrap())
+            // 太大的浮点数会变成inf，它没有对应的字面量，格式化之后无法再读回来
+            let v = sub_str.parse::<f64>().unwrap();
+            if v.is_finite() {
+                Token::from_float(v)
+            } else {
+                Token::Illegal(format!("float literal out of range: {}", sub_str))
+            }
         } else {
-            Token::from_int(sub_str.parse::<i64>().unwrap())
+            // 超出i64范围的整数字面量
+            sub_str.parse::<i64>().map_or_else(
+                |_| Token::Illegal(format!("integer literal out of range: {}", sub_str)),
+                Token::from_int,
+            )
         }
     }
 
-    fn peek_char(&self) -> u8 {
-        if self.read_position >= self.input.as_bytes().len() {
-            return 0;
+    fn read_digits(&mut self) {
+        while self.ch.is_ascii_digit() {
+            self.read_char();
         }
-        return self.input.as_bytes()[self.read_position];
     }
 
+    fn peek_char(&self) -> char {
+        self.peek_char_at(0)
+    }
+
+    // 查看ch之后第n+1个字符
+    fn peek_char_at(&self, n: usize) -> char {
+        self.input
+            .get(self.read_position..self.end)
+            .and_then(|rest| rest.chars().nth(n))
+            .unwrap_or('\0')
+    }
+
+    /// 读取字符串字面量并解码其中的转义序列：`\n \t \r \0 \\ \"`以及`\u{...}`。
+    /// 出错时仍然读到字符串结束的'"'为止，以便后面的token不受影响
     fn read_string(&mut self) -> Token {
-        let pos = self.position + 1;
+        let mut ret = String::new();
+        let mut error = None;
         loop {
             self.read_char();
-            if self.ch == b'\\' && self.peek_char() == b'"' {
+            match self.ch {
+                '"' => break,
+                '\0' if self.at_end() => {
+                    return Token::Illegal("unterminated string literal".to_string());
+                }
+                '\\' => {
+                    self.read_char();
+                    match self.read_escape() {
+                        Ok(ch) => ret.push(ch),
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    }
+                }
+                ch => ret.push(ch),
+            }
+        }
+        match error {
+            Some(e) => Token::Illegal(e),
+            None => Token::String(ret),
+        }
+    }
+
+    /// 读取模板字符串，例如`Hello ${name}`。文本部分的转义和普通字符串相同，另外可以用`\``和`\$`；
+    /// `${...}`中的代码只记录位置，由Parser用sub_lexer解析
+    fn read_template(&mut self) -> Token {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut error = None;
+        loop {
+            self.read_char();
+            match self.ch {
+                '`' => break,
+                '\0' if self.at_end() => {
+                    return Token::Illegal("unterminated template literal".to_string());
+                }
+                '\\' => {
+                    self.read_char();
+                    let escaped = match self.ch {
+                        '`' | '$' => Ok(self.ch),
+                        _ => self.read_escape(),
+                    };
+                    match escaped {
+                        Ok(ch) => text.push(ch),
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    }
+                }
+                '$' if self.peek_char() == '{' => {
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    self.read_char(); // '{'
+                    self.read_char();
+                    let start = self.current_position();
+                    if let Err(e) = self.skip_template_code() {
+                        return Token::Illegal(e);
+                    }
+                    parts.push(TemplatePart::Code(Span::new(
+                        start,
+                        self.current_position(),
+                    )));
+                }
+                ch => text.push(ch),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+        match error {
+            Some(e) => Token::Illegal(e),
+            None => Token::Template(parts),
+        }
+    }
+
+    // 跳过`${`之后的代码，停在与之匹配的'}'上；字符串和嵌套的模板字符串中的大括号不计入
+    fn skip_template_code(&mut self) -> Result<(), String> {
+        let mut depth = 0;
+        loop {
+            match self.ch {
+                '\0' if self.at_end() => {
+                    return Err("unterminated template literal".to_string());
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(()),
+                '}' => depth -= 1,
+                '"' => {
+                    if let Token::Illegal(e) = self.read_string() {
+                        return Err(e);
+                    }
+                }
+                '`' => {
+                    if let Token::Illegal(e) = self.read_template() {
+                        return Err(e);
+                    }
+                }
+                _ => {}
+            }
+            self.read_char();
+        }
+    }
+
+    // 当前字符是'\'之后的字符
+    fn read_escape(&mut self) -> Result<char, String> {
+        match self.ch {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            'u' if self.peek_char() == '{' => {
                 self.read_char();
-            } else if self.ch == b'"' {
-                break;
+                let mut hex = String::new();
+                while self.peek_char().is_ascii_hexdigit() && hex.len() < 6 {
+                    self.read_char();
+                    hex.push(self.ch);
+                }
+                if self.peek_char() != '}' || hex.is_empty() {
+                    return Err("invalid unicode escape, expected `\\u{XXXX}`".to_string());
+                }
+                self.read_char();
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("invalid unicode code point: \\u{{{}}}", hex))
             }
+            // 字符串在'\'之后结束，由read_string报告
+            '\0' if self.at_end() => Err("unterminated string literal".to_string()),
+            ch => Err(format!("unknown escape sequence `\\{}`", ch.escape_debug())),
         }
-        return Token::String(
-            String::from_utf8(self.input.as_bytes()[pos..self.position].to_vec()).unwrap(),
-        );
     }
 }
+
+// 标识符以字母或'_'开头，之后可以有数字
+fn is_identifier_start(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
+}
+
+pub fn is_identifier_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
@@ -1,9 +1,10 @@
 use crate::lexer::lexer::Lexer;
-use crate::lexer::token::Token;
+use crate::lexer::span::Position;
+use crate::lexer::token::{TemplatePart, Token};
 
 #[test]
 fn sign_test() {
-    let mut lx = Lexer::new("={}[]+;");
+    let mut lx = Lexer::new("={}[]+;%**~/<=>=&&||");
     let expects: Vec<Token> = vec![
         Token::Assign,
         Token::LBrace,
@@ -12,11 +13,18 @@ fn sign_test() {
         Token::RBracket,
         Token::Plus,
         Token::Semicolon,
+        Token::Percent,
+        Token::DoubleAsterisk,
+        Token::TildeSlash,
+        Token::LTE,
+        Token::GTE,
+        Token::And,
+        Token::Or,
         Token::EOF,
     ];
 
     for tk in expects {
-        assert_eq!(tk, lx.next_token());
+        assert_eq!(tk, lx.next_token().token);
     }
 }
 
@@ -30,7 +38,7 @@ fn simple_token_test() {
     };
 
     let result = add(five, ten);
-    !-/*5;
+    !-/ *5;
     5 < 10 > 5;
 
     if (5 < 10) {
@@ -131,7 +139,7 @@ fn simple_token_test() {
 
     let mut lx = Lexer::new(input);
     for tk in expects {
-        assert_eq!(tk, lx.next_token());
+        assert_eq!(tk, lx.next_token().token);
     }
 }
 
@@ -139,11 +147,290 @@ fn simple_token_test() {
 fn test_string_token() {
     let cases = [
         ("\"hello\"", Token::String("hello".to_string())),
-        ("\"hello \\\"world\\\"\"", Token::String("hello \\\"world\\\"".to_string())),
+        (
+            "\"hello \\\"world\\\"\"",
+            Token::String("hello \"world\"".to_string()),
+        ),
+        (
+            r#""a\tb\nc\\d\0""#,
+            Token::String("a\tb\nc\\d\0".to_string()),
+        ),
+        (
+            r#""\u{48}\u{e9}\u{1F600}""#,
+            Token::String("Hé😀".to_string()),
+        ),
+        ("\"你好，世界\"", Token::String("你好，世界".to_string())),
+        (
+            r#""\q" 1"#,
+            Token::Illegal("unknown escape sequence `\\q`".to_string()),
+        ),
+        (
+            r#""\u{110000}""#,
+            Token::Illegal("invalid unicode code point: \\u{110000}".to_string()),
+        ),
+        (
+            r#""\u{zz}""#,
+            Token::Illegal("invalid unicode escape, expected `\\u{XXXX}`".to_string()),
+        ),
+        (
+            "\"hello",
+            Token::Illegal("unterminated string literal".to_string()),
+        ),
+        (
+            "\"hello\\",
+            Token::Illegal("unterminated string literal".to_string()),
+        ),
     ];
 
     for (input, expect) in cases {
         let mut lx = Lexer::new(input);
-        assert_eq!(lx.next_token(), expect)
+        assert_eq!(lx.next_token().token, expect)
     }
-}
\ No newline at end of file
+}
+#[test]
+fn test_token_span() {
+    let input = "let x = 10;\n  x + y";
+    let expects = [
+        (Token::Let, (0, 1, 1), (3, 1, 4)),
+        (Token::from_str("x"), (4, 1, 5), (5, 1, 6)),
+        (Token::Assign, (6, 1, 7), (7, 1, 8)),
+        (Token::from_int(10), (8, 1, 9), (10, 1, 11)),
+        (Token::Semicolon, (10, 1, 11), (11, 1, 12)),
+        (Token::from_str("x"), (14, 2, 3), (15, 2, 4)),
+        (Token::Plus, (16, 2, 5), (17, 2, 6)),
+        (Token::from_str("y"), (18, 2, 7), (19, 2, 8)),
+    ];
+
+    let mut lx = Lexer::new(input);
+    for (tk, start, end) in expects {
+        let st = lx.next_token();
+        assert_eq!(st.token, tk);
+        assert_eq!(st.span.start, Position::new(start.0, start.1, start.2));
+        assert_eq!(st.span.end, Position::new(end.0, end.1, end.2));
+    }
+    assert!(lx.next_token().token.is_eof());
+}
+
+#[test]
+fn test_number_token() {
+    let cases = [
+        ("5", Token::from_int(5)),
+        ("2.75", Token::from_float(2.75)),
+        ("1.5e-3", Token::from_float(1.5e-3)),
+        ("2e10", Token::from_float(2e10)),
+        ("2E+5", Token::from_float(2e5)),
+        ("0.25E2", Token::from_float(25.0)),
+    ];
+
+    for (input, expect) in cases {
+        let mut lx = Lexer::new(input);
+        assert_eq!(lx.next_token().token, expect);
+        assert!(lx.next_token().token.is_eof());
+    }
+
+    assert_eq!(
+        Lexer::new("1e400").next_token().token,
+        Token::Illegal("float literal out of range: 1e400".to_string())
+    );
+    // 太小的浮点数是0.0，仍然是合法的字面量
+    assert_eq!(
+        Lexer::new("1e-400").next_token().token,
+        Token::from_float(0.0)
+    );
+
+    // 'e'后面没有数字时不是指数，'.'后面没有数字时也不是小数点
+    let mut lx = Lexer::new("2e 3.");
+    assert_eq!(lx.next_token().token, Token::from_int(2));
+    assert_eq!(lx.next_token().token, Token::from_str("e"));
+    assert_eq!(lx.next_token().token, Token::from_int(3));
+    assert_eq!(
+        lx.next_token().token,
+        Token::Illegal("unexpected character `.`".to_string())
+    );
+}
+
+#[test]
+fn test_range_and_loop_keywords() {
+    let mut lx = Lexer::new("for (i in 0..10) { while break continue }");
+    let expect = [
+        Token::For,
+        Token::LParen,
+        Token::from_str("i"),
+        Token::In,
+        Token::from_int(0),
+        Token::DotDot,
+        Token::from_int(10),
+        Token::RParen,
+        Token::LBrace,
+        Token::While,
+        Token::Break,
+        Token::Continue,
+        Token::RBrace,
+        Token::EOF,
+    ];
+    for token in expect {
+        assert_eq!(lx.next_token().token, token);
+    }
+}
+
+#[test]
+fn test_try_keywords() {
+    let mut lx = Lexer::new("try catch finally throw trying");
+    let expect = [
+        Token::Try,
+        Token::Catch,
+        Token::Finally,
+        Token::Throw,
+        Token::from_str("trying"),
+        Token::EOF,
+    ];
+    for token in expect {
+        assert_eq!(lx.next_token().token, token);
+    }
+}
+
+#[test]
+fn test_identifier_with_underscore() {
+    let mut lx = Lexer::new("has_key _private");
+    assert_eq!(lx.next_token().token, Token::from_str("has_key"));
+    assert_eq!(lx.next_token().token, Token::from_str("_private"));
+}
+
+#[test]
+fn test_assign_operators() {
+    let mut lx = Lexer::new("= += -= *= /= ** == + /");
+    let expect = [
+        Token::Assign,
+        Token::PlusAssign,
+        Token::MinusAssign,
+        Token::AsteriskAssign,
+        Token::SlashAssign,
+        Token::DoubleAsterisk,
+        Token::Eq,
+        Token::Plus,
+        Token::Slash,
+        Token::EOF,
+    ];
+    for token in expect {
+        assert_eq!(lx.next_token().token, token);
+    }
+}
+
+#[test]
+fn test_unicode_source() {
+    let mut lx = Lexer::new("let 名字 = \"é\"; café");
+    let expect = [
+        Token::Let,
+        Token::from_str("名字"),
+        Token::Assign,
+        Token::String("é".to_string()),
+        Token::Semicolon,
+        Token::from_str("café"),
+        Token::EOF,
+    ];
+    for token in expect {
+        assert_eq!(lx.next_token().token, token);
+    }
+
+    // 列号按字符计数
+    let mut lx = Lexer::new("\"é\" x");
+    lx.next_token();
+    let span = lx.next_token().span;
+    assert_eq!((span.start.offset, span.start.column), (5, 5));
+
+    // 出错的字符串之后的token不受影响
+    let mut lx = Lexer::new("\"\\q\" 1 @");
+    assert!(matches!(lx.next_token().token, Token::Illegal(_)));
+    assert_eq!(lx.next_token().token, Token::from_int(1));
+    assert_eq!(
+        lx.next_token().token,
+        Token::Illegal("unexpected character `@`".to_string())
+    );
+}
+
+#[test]
+fn test_template_token() {
+    let input = "`a${x + 1}b\\`${ {1: \"}\"}[1] }`";
+    let mut lx = Lexer::new(input);
+    let token = lx.next_token().token;
+    let parts = match token {
+        Token::Template(parts) => parts,
+        token => panic!("expect template, got {:?}", token),
+    };
+    let parts = parts
+        .iter()
+        .map(|part| match part {
+            TemplatePart::Text(text) => text.clone(),
+            TemplatePart::Code(span) => format!(
+                "{}@{}",
+                &input[span.start.offset..span.end.offset],
+                span.start
+            ),
+        })
+        .collect::<Vec<String>>();
+    assert_eq!(parts, vec!["a", "x + 1@1:5", "b`", " {1: \"}\"}[1] @1:16"]);
+    assert!(lx.next_token().token.is_eof());
+
+    for (input, expect) in [
+        ("`abc", "unterminated template literal"),
+        ("`a${b`", "unterminated template literal"),
+        ("`\\q`", "unknown escape sequence `\\q`"),
+    ] {
+        let mut lx = Lexer::new(input);
+        assert_eq!(lx.next_token().token, Token::Illegal(expect.to_string()));
+    }
+}
+
+#[test]
+fn test_comments() {
+    let input =
+        "#!/usr/bin/env monkey\nlet a = 1; // 行注释 */\n/* 块注释 /* 可以嵌套 */ a */ a / 2 /**/";
+    let mut lx = Lexer::new(input);
+    let expect = [
+        Token::Let,
+        Token::from_str("a"),
+        Token::Assign,
+        Token::from_int(1),
+        Token::Semicolon,
+        Token::from_str("a"),
+        Token::Slash,
+        Token::from_int(2),
+        Token::EOF,
+    ];
+    for token in expect {
+        assert_eq!(lx.next_token().token, token);
+    }
+
+    let mut lx = Lexer::new("1 /* a /* b */");
+    assert_eq!(lx.next_token().token, Token::from_int(1));
+    let spanned = lx.next_token();
+    assert_eq!(
+        spanned.token,
+        Token::Illegal("unterminated block comment".to_string())
+    );
+    assert_eq!(spanned.span.start.column, 3);
+
+    // 只有第一行的#!才是shebang
+    let mut lx = Lexer::new("1\n#!");
+    assert_eq!(lx.next_token().token, Token::from_int(1));
+    assert_eq!(
+        lx.next_token().token,
+        Token::Illegal("unexpected character `#`".to_string())
+    );
+}
+
+#[test]
+fn test_identifier_with_digits() {
+    let mut lx = Lexer::new("player_hp item2 _3d 2x");
+    let expect = [
+        Token::from_str("player_hp"),
+        Token::from_str("item2"),
+        Token::from_str("_3d"),
+        Token::from_int(2),
+        Token::from_str("x"),
+        Token::EOF,
+    ];
+    for token in expect {
+        assert_eq!(lx.next_token().token, token);
+    }
+}
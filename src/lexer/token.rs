@@ -1,29 +1,56 @@
+use crate::lexer::span::Span;
+use std::fmt::{Display, Formatter};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
-    Illegal,
+    Illegal(String), // 无法识别的输入，内容是原因
     EOF,
 
     // keywords
     Let,      // let
+    Const,    // const
     Function, // fn
     If,       // if
     Else,     //else
     Return,   //return
+    Null,     // null
+    While,    // while
+    For,      // for
+    In,       // in
+    Break,    // break
+    Continue, // continue
+    Try,      // try
+    Catch,    // catch
+    Finally,  // finally
+    Throw,    // throw
 
     // sign
-    Assign,    // =
-    Plus,      // +
-    Minus,     // -
-    Bang,      // !
-    Asterisk,  // *
-    Slash,     // /
-    LT,        // <
-    GT,        // >
-    Comma,     // ,
-    Semicolon, // ;
-    Colon,     // :
-    Eq,        // ==
-    NotEq,     // !=
+    Assign,         // =
+    PlusAssign,     // +=
+    MinusAssign,    // -=
+    AsteriskAssign, // *=
+    SlashAssign,    // /=
+    Plus,           // +
+    Minus,          // -
+    Bang,           // !
+    Asterisk,       // *
+    Slash,          // /
+    Percent,        // %
+    DoubleAsterisk, // **
+    TildeSlash,     // ~/
+    LT,             // <
+    GT,             // >
+    LTE,            // <=
+    GTE,            // >=
+    And,            // &&
+    Or,             // ||
+    Comma,          // ,
+    Semicolon,      // ;
+    Colon,          // :
+    DotDot,         // ..
+    Ellipsis,       // ...
+    Eq,             // ==
+    NotEq,          // !=
 
     LParen,   // (
     RParen,   // )
@@ -37,23 +64,65 @@ pub enum Token {
     Int(i64),
     Float(f64),
     String(String),
+    Template(Vec<TemplatePart>),
+}
+
+/// 模板字符串的组成部分：文本，或者`${...}`中代码的位置
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TemplatePart {
+    Text(String),
+    Code(Span),
 }
 
 impl Eq for Token {}
 
 pub const EOF_TOKEN: Token = Token::EOF;
 
+/// 所有关键字，true/false也作为关键字对待
+pub const KEYWORDS: &[&str] = &[
+    "fn", "let", "const", "true", "false", "if", "else", "return", "null", "while", "for", "in",
+    "break", "continue", "try", "catch", "finally", "throw",
+];
+
+/// 带有源码位置信息的Token，由`Lexer::next_token`产生
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl Eq for SpannedToken {}
+
+/// 源码中的注释，text包含`//`、`/* */`或者第一行的`#!`本身。
+/// 解释执行时用不到，由Lexer收集起来给格式化工具保留注释
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
 impl Token {
     pub fn from_str<T: Into<String>>(input: T) -> Token {
         let input = input.into();
         match input.as_str() {
             "fn" => Token::Function,
             "let" => Token::Let,
+            "const" => Token::Const,
             "true" => Token::Bool(true),
             "false" => Token::Bool(false),
             "if" => Token::If,
             "else" => Token::Else,
             "return" => Token::Return,
+            "null" => Token::Null,
+            "while" => Token::While,
+            "for" => Token::For,
+            "in" => Token::In,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "try" => Token::Try,
+            "catch" => Token::Catch,
+            "finally" => Token::Finally,
+            "throw" => Token::Throw,
             _ => Token::Ident(input),
         }
     }
@@ -76,22 +145,68 @@ impl Token {
     //         _ => false,
     //     }
     // }
+}
 
-    pub fn to_string(&self) -> String {
-        match self {
-            Token::Plus => "+".to_string(),
-            Token::Minus => "-".to_string(),
-            Token::Bang => "!".to_string(),
-            Token::Asterisk => "*".to_string(),
-            Token::Slash => "/".to_string(),
-            Token::LT => "<".to_string(),
-            Token::GT => ">".to_string(),
-            Token::Comma => ",".to_string(),
-            Token::Colon => ":".to_string(),
-            Token::Semicolon => ";".to_string(),
-            Token::Eq => "==".to_string(),
-            Token::NotEq => "!=".to_string(),
-            _ => "".to_string(),
-        }
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Token::Illegal(_) => "ILLEGAL",
+            Token::EOF => "EOF",
+            Token::Let => "let",
+            Token::Const => "const",
+            Token::Function => "fn",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::Return => "return",
+            Token::Null => "null",
+            Token::While => "while",
+            Token::For => "for",
+            Token::In => "in",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Try => "try",
+            Token::Catch => "catch",
+            Token::Finally => "finally",
+            Token::Throw => "throw",
+            Token::Assign => "=",
+            Token::PlusAssign => "+=",
+            Token::MinusAssign => "-=",
+            Token::AsteriskAssign => "*=",
+            Token::SlashAssign => "/=",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Bang => "!",
+            Token::Asterisk => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::DoubleAsterisk => "**",
+            Token::TildeSlash => "~/",
+            Token::LT => "<",
+            Token::GT => ">",
+            Token::LTE => "<=",
+            Token::GTE => ">=",
+            Token::And => "&&",
+            Token::Or => "||",
+            Token::Comma => ",",
+            Token::Colon => ":",
+            Token::DotDot => "..",
+            Token::Ellipsis => "...",
+            Token::Semicolon => ";",
+            Token::Eq => "==",
+            Token::NotEq => "!=",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::LBrace => "{",
+            Token::RBrace => "}",
+            Token::Ident(v) => v,
+            Token::Bool(v) => return write!(f, "{}", v),
+            Token::Int(v) => return write!(f, "{}", v),
+            Token::Float(v) => return write!(f, "{}", v),
+            Token::String(v) => return write!(f, "\"{}\"", v),
+            Token::Template(_) => "template string",
+        };
+        f.write_str(s)
     }
 }
@@ -1,51 +1,43 @@
-use std::io::Write;
-
-use async_ctrlc::CtrlC;
-use async_std::prelude::*;
+// 语法树和Token的命名沿用《Writing An Interpreter In Go》中的叫法。
+#![allow(
+    clippy::enum_variant_names,
+    clippy::upper_case_acronyms,
+    clippy::module_inception
+)]
 
-use crate::lexer::lexer::Lexer;
-use crate::parser::Parser;
-use crate::eval::environment::Environment;
-use crate::eval::evaluator::Evaluator;
+use std::io::Write;
 
+mod cli;
+mod error;
 mod eval;
 mod lexer;
 mod parser;
+mod repl;
 
-const PROMPT: &str = ">>";
-
-#[async_std::main]
-async fn main() {
+fn main() {
     env_logger::init();
 
-    let ctrlc = CtrlC::new().expect("cannot create Ctrl+C handler!");
-    println!("Welcome to Monkey Language REPL, press Ctrl+C to quit.");
-
-    ctrlc
-        .race(async {
-            let mut env = Environment::default();
-
-            loop {
-                print!("{}", PROMPT);
-                std::io::stdout().flush().unwrap();
+    let interpreter = std::thread::Builder::new()
+        .stack_size(eval::STACK_SIZE)
+        .spawn(run)
+        .expect("failed to spawn the interpreter thread");
+    match interpreter.join() {
+        Ok(Some(code)) => {
+            std::io::stdout().flush().unwrap();
+            std::process::exit(code);
+        }
+        Ok(None) => {}
+        // panic的信息已经输出过了，退出码和主线程panic时一样
+        Err(_) => std::process::exit(101),
+    }
+}
 
-                let mut buf = String::new();
-                async_std::io::stdin().read_line(&mut buf).await.unwrap();
+fn run() -> Option<i32> {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if let Some(code) = cli::dispatch(&args) {
+        return Some(code);
+    }
 
-                let lx = Lexer::new(buf);
-                let mut p = Parser::new(lx);
-                if let Err(e) = p.parse_program().and_then(|program| {
-                    let mut evaluator = Evaluator::with_env(&program.statements, env.clone());
-                    evaluator.eval().and_then(|obj| {
-                        println!("{:?}", obj);
-                        env = evaluator.get_env();
-                        Ok(())
-                    })
-                }) {
-                    eprintln!("{:?}", e);
-                }
-            }
-        })
-        .await;
-    println!("\nCtrl+C pressed, quiting")
+    repl::start();
+    None
 }
@@ -0,0 +1,160 @@
+use crate::error::{Error, ErrorKind};
+use crate::lexer::span::Span;
+
+/// 把错误渲染成带源码片段的诊断信息，例如：
+///
+/// ```text
+/// error: identifier not found: lett
+///  --> main.mk:1:1
+///   |
+/// 1 | lett x = 5;
+///   | ^^^^
+///   = help: did you mean `let`?
+/// ```
+///
+/// 在函数中发生的运行时错误，最后还有出错时的调用栈，见[`traceback`]
+pub fn render(error: &Error, source: &str) -> String {
+    let mut ret = format!("error: {}\n", error.info());
+
+    let span = match error.span() {
+        Some(span) => span,
+        None => {
+            for help in error.help() {
+                ret.push_str(&format!("  = help: {}\n", help));
+            }
+            ret.push_str(&traceback(error));
+            return ret;
+        }
+    };
+
+    let line_no = span.start.line.to_string();
+    let gutter = " ".repeat(line_no.len());
+    match error.file() {
+        Some(file) => ret.push_str(&format!("{}--> {}:{}\n", gutter, file, span.start)),
+        None => ret.push_str(&format!("{}--> {}\n", gutter, span.start)),
+    }
+
+    if let Some((line_start, start, end, line_end)) = locate(source, span) {
+        let line = source[line_start..line_end].trim_end_matches('\r');
+        let padding = source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let width = std::cmp::max(1, source[start..end].chars().count());
+
+        ret.push_str(&format!("{} |\n", gutter));
+        ret.push_str(&format!("{} | {}\n", line_no, line));
+        ret.push_str(&format!("{} | {}{}", gutter, padding, "^".repeat(width)));
+        if let Some(label) = label(error) {
+            ret.push(' ');
+            ret.push_str(&label);
+        }
+        ret.push('\n');
+    }
+
+    for help in error.help() {
+        ret.push_str(&format!("{} = help: {}\n", gutter, help));
+    }
+    ret.push_str(&traceback(error));
+    ret
+}
+
+/// 出错时的调用栈，最内层的调用在前，每一行是函数名和调用它的位置；不在函数中时为空：
+///
+/// ```text
+/// traceback (most recent call first):
+///   0: inner at main.mk:5:21
+///   1: outer at main.mk:8:1
+/// ```
+///
+/// 递归调用中连续重复的同一个调用只输出一次
+pub fn traceback(error: &Error) -> String {
+    let frames = error.frames();
+    if frames.is_empty() {
+        return String::new();
+    }
+
+    let mut ret = "traceback (most recent call first):\n".to_string();
+    let mut i = 0;
+    while i < frames.len() {
+        ret.push_str(&format!("  {}: {}\n", i, frames[i]));
+        let repeated = frames[i + 1..]
+            .iter()
+            .take_while(|frame| *frame == &frames[i])
+            .count();
+        if repeated > 0 {
+            ret.push_str(&format!("     ... repeated {} more times\n", repeated));
+        }
+        i += repeated + 1;
+    }
+    ret
+}
+
+// span在source中的位置：所在行的起止，以及要标记的起止（跨行的span只标记到第一行的行尾）。
+// span来自另一段源码时（偏移不在字符边界上或者行列对不上）返回None，不输出源码片段
+fn locate(source: &str, span: Span) -> Option<(usize, usize, usize, usize)> {
+    let start = span.start.offset;
+    if start > source.len() || !source.is_char_boundary(start) {
+        return None;
+    }
+    let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = source[..start].matches('\n').count() + 1;
+    let column = source[line_start..start].chars().count() + 1;
+    if line != span.start.line || column != span.start.column {
+        return None;
+    }
+
+    let line_end = source[start..]
+        .find('\n')
+        .map(|i| start + i)
+        .unwrap_or(source.len());
+    let mut end = std::cmp::max(start, std::cmp::min(span.end.offset, line_end));
+    while !source.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some((line_start, start, end, line_end))
+}
+
+fn label(error: &Error) -> Option<String> {
+    match (error.kind(), error.expected(), error.found()) {
+        (ErrorKind::UnexpectedToken, Some(expected), Some(found)) => {
+            Some(format!("expected `{}`, found `{}`", expected, found))
+        }
+        (ErrorKind::NoPrefixParse, _, Some(found)) => Some(format!("unexpected `{}`", found)),
+        (ErrorKind::IllegalToken, _, _) => Some("not a valid token".to_string()),
+        _ => None,
+    }
+}
+
+/// 在candidates中找出和name最接近的一个，用于"did you mean"提示
+pub fn suggest<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    // 太短的名字给出的建议基本都是噪音
+    let max_distance = name.chars().count() / 3;
+    if max_distance == 0 {
+        return None;
+    }
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(one: &str, two: &str) -> usize {
+    let two = two.chars().collect::<Vec<char>>();
+    let mut prev = (0..=two.len()).collect::<Vec<usize>>();
+    for (i, c1) in one.chars().enumerate() {
+        let mut cur = vec![i + 1; two.len() + 1];
+        for (j, c2) in two.iter().enumerate() {
+            let cost = if c1 == *c2 { 0 } else { 1 };
+            cur[j + 1] = std::cmp::min(std::cmp::min(prev[j + 1] + 1, cur[j] + 1), prev[j] + cost);
+        }
+        prev = cur;
+    }
+    prev[two.len()]
+}
@@ -0,0 +1,732 @@
+use crate::error::Result;
+use crate::eval::quote;
+use crate::lexer::lexer::Lexer;
+use crate::lexer::span::{Position, Span};
+use crate::lexer::token::{Comment, SpannedToken, Token};
+use crate::parser::program::{
+    Expression, ExpressionKind, Parameter, Precedence, Statement, StatementKind, TemplateSegment,
+};
+use crate::parser::Parser;
+
+const INDENT: &str = "    ";
+/// 超过这个宽度的参数、数组和Hash拆成每个元素一行
+pub const MAX_WIDTH: usize = 100;
+
+/// 把源码格式化成统一的风格，有语法错误时返回错误。
+///
+/// 每条语句占一行，块缩进4个空格，表达式只保留必要的括号；
+/// 注释、语句之间的空行（最多保留一行）以及`else if`的写法会被保留。
+/// 格式化的结果再次格式化不会有任何变化。
+pub fn format(source: &str) -> Result<String> {
+    let program = Parser::new(Lexer::new(source)).parse_program()?;
+
+    // 注释不在语法树中，而块的'{'和'}'的位置也只能从token中得到
+    let mut lx = Lexer::new(source);
+    let mut tokens = vec![];
+    loop {
+        let token = lx.next_token();
+        if token.token.is_eof() {
+            break;
+        }
+        tokens.push(token);
+    }
+    let comments = lx.take_comments();
+
+    let mut formatter = Formatter {
+        tokens,
+        emitted: vec![false; comments.len()],
+        comments,
+    };
+    Ok(formatter.statements(&program.statements, Some((0, source.len())), 0))
+}
+
+struct Formatter {
+    tokens: Vec<SpannedToken>,
+    comments: Vec<Comment>,
+    // 每个注释是否已经输出
+    emitted: Vec<bool>,
+}
+
+// 列表中的元素
+enum Item<'a> {
+    Expression(&'a Expression),
+    Parameter(&'a Parameter),
+    Pair(&'a Expression, &'a Expression),
+}
+
+impl Formatter {
+    // 输出块中的语句，每条语句一行并以换行结束。range是块在源码中'{'和'}'之间的范围，
+    // 用来收集其中的注释；模板字符串中的代码没有对应的token，range为None
+    fn statements(
+        &mut self,
+        body: &[Statement],
+        range: Option<(usize, usize)>,
+        level: usize,
+    ) -> String {
+        let mut out = String::new();
+        let mut last_line = None;
+        // 省略了';'的if表达式语句，如果后一条语句以'('、'['或'-'开头，就会被当成调用、下标或者减法
+        let mut pending_semicolon = None;
+
+        for (i, st) in body.iter().enumerate() {
+            let next_start = match (body.get(i + 1), range) {
+                (Some(next), _) => next.span.start.offset,
+                (None, Some((_, end))) => end,
+                (None, None) => st.span.end.offset,
+            };
+
+            if let Some(range) = range {
+                while let Some(c) = self.next_comment(range.0, st.span.start.offset) {
+                    self.comment_line(&mut out, c, level, &mut last_line);
+                }
+            }
+            blank_line(&mut out, last_line, st.span.start.line);
+
+            // 块中最后一个表达式语句是块的值，不需要';'
+            let is_value = level > 0 && i + 1 == body.len();
+            let text = self.statement(st, level, is_value);
+            if let Some(pos) = pending_semicolon.take() {
+                if text.starts_with(['(', '[', '-']) {
+                    out.insert(pos, ';');
+                }
+            }
+            out.push_str(&indent(level));
+            out.push_str(&text);
+            if !is_value && is_if_statement(st) {
+                pending_semicolon = Some(out.len());
+            }
+
+            // 语句中没有被块和列表收走的注释，例如运算符两边的注释，放到语句之后
+            let inner = match range {
+                Some(_) => self.take_comments(st.span.start.offset, st.span.end.offset),
+                None => vec![],
+            };
+            let mut end_line = st.span.end.line;
+            if let Some(c) = self.next_comment(st.span.end.offset, next_start) {
+                if self.comments[c].span.start.line == st.span.end.line {
+                    out.push(' ');
+                    out.push_str(comment_text(&self.comments[c]));
+                    end_line = self.comments[c].span.end.line;
+                    self.emitted[c] = true;
+                }
+            }
+            out.push('\n');
+            for c in inner {
+                out.push_str(&indent(level));
+                out.push_str(comment_text(&self.comments[c]));
+                out.push('\n');
+            }
+            last_line = Some(end_line);
+        }
+
+        if let Some(range) = range {
+            while let Some(c) = self.next_comment(range.0, range.1) {
+                self.comment_line(&mut out, c, level, &mut last_line);
+            }
+        }
+        out
+    }
+
+    // 从'{'到'}'的块，level是'}'所在的缩进层级
+    fn block(&mut self, body: &[Statement], range: Option<(usize, usize)>, level: usize) -> String {
+        let inner = self.statements(body, range, level + 1);
+        if inner.is_empty() {
+            "{}".to_string()
+        } else {
+            format!("{{\n{}{}}}", inner, indent(level))
+        }
+    }
+
+    // 不含开头缩进的语句，多行时后面各行带有完整的缩进
+    fn statement(&mut self, st: &Statement, level: usize, is_value: bool) -> String {
+        let column = level * INDENT.len();
+        match &st.kind {
+            StatementKind::LetStatement(ident, value) => {
+                let head = format!("let {} = ", ident.0);
+                let value = self.expression(value, level, end_column(column, &head));
+                format!("{}{};", head, value)
+            }
+            StatementKind::ConstStatement(ident, value) => {
+                let head = format!("const {} = ", ident.0);
+                let value = self.expression(value, level, end_column(column, &head));
+                format!("{}{};", head, value)
+            }
+            StatementKind::ReturnStatement(value) => {
+                format!("return {};", self.expression(value, level, column + 7))
+            }
+            StatementKind::ExpressionStatement(expr) => {
+                let text = self.expression(expr, level, column);
+                if is_value || is_if_statement(st) {
+                    text
+                } else {
+                    text + ";"
+                }
+            }
+            StatementKind::WhileStatement(condition, body) => {
+                let condition_text = self.expression(condition, level, column + 7);
+                let range = self.block_range(condition.span.end.offset, st.span.end.offset, false);
+                format!(
+                    "while ({}) {}",
+                    condition_text,
+                    self.block(body, range, level)
+                )
+            }
+            StatementKind::ForStatement(ident, iterable, body) => {
+                let head = format!("for ({} in ", ident.0);
+                let iterable_text = self.expression(iterable, level, end_column(column, &head));
+                let range = self.block_range(iterable.span.end.offset, st.span.end.offset, false);
+                format!(
+                    "{}{}) {}",
+                    head,
+                    iterable_text,
+                    self.block(body, range, level)
+                )
+            }
+            StatementKind::BreakStatement => "break;".to_string(),
+            StatementKind::ContinueStatement => "continue;".to_string(),
+            StatementKind::FunctionStatement(name, params, body) => {
+                let head = format!("fn {}", name.0);
+                let range =
+                    self.list_range(st.span.start.offset, st.span.end.offset, Token::LParen);
+                let params = self.parameters(params, level, end_column(column, &head), range);
+                let range = self.block_range(st.span.start.offset, st.span.end.offset, true);
+                format!("{}{} {}", head, params, self.block(body, range, level))
+            }
+            StatementKind::ThrowStatement(value) => {
+                format!("throw {};", self.expression(value, level, column + 6))
+            }
+            StatementKind::TryStatement(body, catch, finally) => {
+                // 每个块都从上一个块的'}'之后开始找
+                let end = st.span.end.offset;
+                let mut range = self.block_range(st.span.start.offset, end, false);
+                let mut ret = format!("try {}", self.block(body, range, level));
+                if let Some((ident, handler)) = catch {
+                    range = range.and_then(|(_, close)| self.block_range(close + 1, end, false));
+                    let handler = self.block(handler, range, level);
+                    ret.push_str(&format!(" catch ({}) {}", ident.0, handler));
+                }
+                if let Some(finally) = finally {
+                    range = range.and_then(|(_, close)| self.block_range(close + 1, end, false));
+                    ret.push_str(&format!(" finally {}", self.block(finally, range, level)));
+                }
+                ret
+            }
+        }
+    }
+
+    // 从column列开始输出的表达式
+    fn expression(&mut self, expr: &Expression, level: usize, column: usize) -> String {
+        match &expr.kind {
+            ExpressionKind::Identifier(ident) => ident.0.clone(),
+            ExpressionKind::IntLiteral(v) => v.to_string(),
+            ExpressionKind::FloatLiteral(v) => format!("{:?}", v),
+            ExpressionKind::BoolLiteral(v) => v.to_string(),
+            ExpressionKind::StringLiteral(v) => quote(v),
+            ExpressionKind::NullLiteral => "null".to_string(),
+            ExpressionKind::TemplateLiteral(segments) => self.template(segments, level),
+            ExpressionKind::PrefixExpression(operator, right) => {
+                // `-`的操作数按Call的优先级解析，`-f(x)`是`(-f)(x)`，`--x`是错误
+                let precedence = match operator {
+                    Token::Minus => Precedence::Call,
+                    _ => Precedence::Power,
+                };
+                let right = self.operand(right, precedence, level, column + 1);
+                format!("{}{}", operator, right)
+            }
+            ExpressionKind::InfixExpression(left, operator, right)
+            | ExpressionKind::AssignExpression(left, operator, right) => {
+                let precedence = Precedence::from_token(operator);
+                // '**'和赋值是右结合的
+                let (left_precedence, right_precedence) =
+                    if *operator == Token::DoubleAsterisk || precedence == Precedence::Assign {
+                        (precedence.clone(), precedence.sub(1))
+                    } else {
+                        (precedence.sub(1), precedence)
+                    };
+                let left = self.operand(left, left_precedence, level, column);
+                let operator = match operator {
+                    Token::DotDot => operator.to_string(),
+                    _ => format!(" {} ", operator),
+                };
+                let right = self.operand(
+                    right,
+                    right_precedence,
+                    level,
+                    end_column(column, &left) + operator.len(),
+                );
+                format!("{}{}{}", left, operator, right)
+            }
+            ExpressionKind::CallExpression(function_expr, args) => {
+                let function = self.operand(function_expr, Precedence::Prefix, level, column);
+                let items = args.iter().map(Item::Expression).collect::<Vec<Item>>();
+                let range = self.list_range(
+                    function_expr.span.end.offset,
+                    expr.span.end.offset,
+                    Token::LParen,
+                );
+                let args = self.list(
+                    "(",
+                    &items,
+                    ")",
+                    level,
+                    end_column(column, &function),
+                    range,
+                );
+                function + &args
+            }
+            ExpressionKind::IndexExpression(left, index) => {
+                let left = self.operand(left, Precedence::Prefix, level, column);
+                let index = self.expression(index, level, end_column(column, &left) + 1);
+                format!("{}[{}]", left, index)
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                let items = elements.iter().map(Item::Expression).collect::<Vec<Item>>();
+                let range = self.list_range(
+                    expr.span.start.offset,
+                    expr.span.end.offset,
+                    Token::LBracket,
+                );
+                self.list("[", &items, "]", level, column, range)
+            }
+            ExpressionKind::HashLiteral(pairs) => {
+                let items = pairs
+                    .iter()
+                    .map(|(key, value)| Item::Pair(key, value))
+                    .collect::<Vec<Item>>();
+                let range =
+                    self.list_range(expr.span.start.offset, expr.span.end.offset, Token::LBrace);
+                self.list("{", &items, "}", level, column, range)
+            }
+            ExpressionKind::SpreadExpression(expr) => {
+                format!("...{}", self.expression(expr, level, column + 3))
+            }
+            ExpressionKind::NamedArgument(name, expr) => {
+                let head = format!("{}: ", name.0);
+                let value = self.expression(expr, level, end_column(column, &head));
+                head + &value
+            }
+            ExpressionKind::FunctionExpression(params, body) => {
+                let range =
+                    self.list_range(expr.span.start.offset, expr.span.end.offset, Token::LParen);
+                let params = self.parameters(params, level, column + 2, range);
+                let range = self.block_range(expr.span.start.offset, expr.span.end.offset, true);
+                format!("fn{} {}", params, self.block(body, range, level))
+            }
+            ExpressionKind::IfExpression(condition, consequence, alternative) => {
+                let condition_text = self.expression(condition, level, column + 4);
+                let end = expr.span.end.offset;
+                let range = self.block_range(condition.span.end.offset, end, false);
+                let mut ret = format!(
+                    "if ({}) {}",
+                    condition_text,
+                    self.block(consequence, range, level)
+                );
+                // `}`之后是else，再之后是if还是'{'决定了是不是`else if`
+                let else_if = match range {
+                    Some((_, close)) => self
+                        .tokens
+                        .get(self.token_at(close) + 2)
+                        .is_some_and(|token| token.token == Token::If),
+                    None => true,
+                };
+                let else_range =
+                    range.and_then(|(_, close)| self.block_range(close + 1, end, false));
+                // 空的else块只在其中有注释时保留
+                if alternative.is_empty()
+                    && else_range
+                        .is_none_or(|(open, close)| self.next_comment(open, close).is_none())
+                {
+                    return ret;
+                }
+
+                ret.push_str(" else ");
+                match alternative.as_slice() {
+                    [Statement {
+                        kind: StatementKind::ExpressionStatement(expr),
+                        ..
+                    }] if else_if && matches!(expr.kind, ExpressionKind::IfExpression(..)) => {
+                        let column = end_column(column, &ret);
+                        ret.push_str(&self.expression(expr, level, column));
+                    }
+                    _ => ret.push_str(&self.block(alternative, else_range, level)),
+                }
+                ret
+            }
+        }
+    }
+
+    // 放在要求优先级高于precedence的位置上的表达式，必要时加上括号
+    fn operand(
+        &mut self,
+        expr: &Expression,
+        precedence: Precedence,
+        level: usize,
+        column: usize,
+    ) -> String {
+        if binding_precedence(expr) <= precedence {
+            format!("({})", self.expression(expr, level, column + 1))
+        } else {
+            self.expression(expr, level, column)
+        }
+    }
+
+    fn parameters(
+        &mut self,
+        params: &[Parameter],
+        level: usize,
+        column: usize,
+        range: Option<(Position, Position)>,
+    ) -> String {
+        let items = params.iter().map(Item::Parameter).collect::<Vec<Item>>();
+        self.list("(", &items, ")", level, column, range)
+    }
+
+    // 逗号分隔的列表，一行放不下或者中间的元素有多行时，每个元素占一行。
+    // range是列表在源码中的开始和结束括号之间的范围，用来找到元素之间的注释
+    fn list(
+        &mut self,
+        open: &str,
+        items: &[Item],
+        close: &str,
+        level: usize,
+        column: usize,
+        range: Option<(Position, Position)>,
+    ) -> String {
+        if let Some((start, end)) = range {
+            let spans = self.item_spans(start, end);
+            if spans.len() == items.len() && self.has_gap_comments(start, end, &spans) {
+                return self.commented_list(open, items, close, level, (start, end), &spans);
+            }
+        }
+
+        let emitted = self.emitted.clone();
+        let mut ret = open.to_string();
+        let mut multiline = false;
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                ret.push_str(", ");
+            }
+            let text = self.item(item, level, end_column(column, &ret));
+            // 最后一个元素可以跨行，例如作为最后一个参数的函数
+            multiline |= i + 1 < items.len() && text.contains('\n');
+            ret.push_str(&text);
+        }
+        ret.push_str(close);
+        if items.is_empty() || (!multiline && fits(column, &ret)) {
+            return ret;
+        }
+
+        // 重新输出时块中的注释还要再收集一次
+        self.emitted = emitted;
+        let inner = indent(level + 1);
+        let mut ret = format!("{}\n", open);
+        for (i, item) in items.iter().enumerate() {
+            let text = self.item(item, level + 1, inner.len());
+            ret.push_str(&inner);
+            ret.push_str(&text);
+            ret.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+        }
+        ret.push_str(&indent(level));
+        ret.push_str(close);
+        ret
+    }
+
+    // 元素之间有注释的列表总是每个元素占一行。和前一个元素在同一行的注释跟在它的','之后，
+    // 和后一个元素在同一行的块注释放在它前面，其余的注释（包括'('、'['、'{'之后的）单独占一行
+    fn commented_list(
+        &mut self,
+        open: &str,
+        items: &[Item],
+        close: &str,
+        level: usize,
+        (start, end): (Position, Position),
+        spans: &[Span],
+    ) -> String {
+        let inner = indent(level + 1);
+        let mut lines = vec![open.to_string()];
+        let mut prefix = String::new();
+        let mut prev_end = start;
+        for i in 0..=items.len() {
+            let next_start = spans.get(i).map_or(end, |span| span.start);
+            for c in self.take_comments(prev_end.offset, next_start.offset) {
+                let comment = &self.comments[c];
+                let text = comment_text(comment);
+                if i < items.len() && comment.span.end.line == next_start.line {
+                    prefix.push_str(text);
+                    prefix.push(' ');
+                } else if i > 0 && comment.span.start.line == prev_end.line {
+                    let last = lines.last_mut().unwrap();
+                    last.push(' ');
+                    last.push_str(text);
+                } else {
+                    lines.push(format!("{}{}", inner, text));
+                }
+            }
+
+            if let Some(item) = items.get(i) {
+                let column = end_column(inner.len(), &prefix);
+                let text = self.item(item, level + 1, column);
+                let comma = if i + 1 < items.len() { "," } else { "" };
+                lines.push(format!("{}{}{}{}", inner, prefix, text, comma));
+                prefix.clear();
+                prev_end = spans[i].end;
+            }
+        }
+        lines.push(format!("{}{}", indent(level), close));
+        lines.join("\n")
+    }
+
+    fn item(&mut self, item: &Item, level: usize, column: usize) -> String {
+        match item {
+            Item::Expression(expr) => self.expression(expr, level, column),
+            Item::Parameter(param) => {
+                let mut ret = String::new();
+                if param.rest {
+                    ret.push_str("...");
+                }
+                ret.push_str(&param.name.0);
+                if let Some(default) = &param.default {
+                    ret.push_str(" = ");
+                    let column = end_column(column, &ret);
+                    ret.push_str(&self.expression(default, level, column));
+                }
+                ret
+            }
+            Item::Pair(key, value) => {
+                let key = self.expression(key, level, column);
+                let value = self.expression(value, level, end_column(column, &key) + 2);
+                format!("{}: {}", key, value)
+            }
+        }
+    }
+
+    fn template(&mut self, segments: &[TemplateSegment], level: usize) -> String {
+        let mut ret = "`".to_string();
+        for segment in segments {
+            match segment {
+                TemplateSegment::Text(text) => {
+                    let mut chars = text.chars().peekable();
+                    while let Some(ch) = chars.next() {
+                        match ch {
+                            '`' | '\\' => ret.push('\\'),
+                            '$' if chars.peek() == Some(&'{') => ret.push('\\'),
+                            _ => {}
+                        }
+                        ret.push(ch);
+                    }
+                }
+                TemplateSegment::Expression(expr) => {
+                    ret.push_str("${");
+                    ret.push_str(&self.expression(expr, level, 0));
+                    ret.push('}');
+                }
+            }
+        }
+        ret.push('`');
+        ret
+    }
+
+    // 第一个起始位置不小于offset的token
+    fn token_at(&self, offset: usize) -> usize {
+        self.tokens
+            .partition_point(|token| token.span.start.offset < offset)
+    }
+
+    // 从from开始的第一个块在'{'和'}'之间的范围，块必须在end之前结束。
+    // 函数的参数默认值中可能有Hash，所以skip_params为true时先跳过一对括号
+    fn block_range(&self, from: usize, end: usize, skip_params: bool) -> Option<(usize, usize)> {
+        let mut i = self.token_at(from);
+        if skip_params {
+            let mut depth = 0;
+            loop {
+                match self.tokens.get(i)?.token {
+                    Token::LParen => depth += 1,
+                    Token::RParen => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+        }
+        while self.tokens.get(i)?.token != Token::LBrace {
+            i += 1;
+        }
+
+        let open = self.tokens[i].span.end.offset;
+        let close = self.tokens[self.closing(i)?].span.start.offset;
+        if close < end {
+            Some((open, close))
+        } else {
+            None
+        }
+    }
+
+    // 从from开始的第一个以open开头的列表在两个括号之间的范围，列表必须在end之前开始和结束。
+    // 模板字符串中的代码没有对应的token，找到的是模板之后的token，因此返回None
+    fn list_range(&self, from: usize, end: usize, open: Token) -> Option<(Position, Position)> {
+        let mut i = self.token_at(from);
+        loop {
+            let token = self.tokens.get(i)?;
+            if token.span.start.offset >= end {
+                return None;
+            }
+            if token.token == open {
+                break;
+            }
+            i += 1;
+        }
+
+        let close = &self.tokens[self.closing(i)?];
+        if close.span.start.offset < end {
+            Some((self.tokens[i].span.end, close.span.start))
+        } else {
+            None
+        }
+    }
+
+    // 第i个token是开始的括号，返回与它配对的结束括号
+    fn closing(&self, i: usize) -> Option<usize> {
+        let mut depth = 0;
+        for (j, token) in self.tokens.iter().enumerate().skip(i) {
+            match token.token {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(j);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // 列表中每个元素从第一个token到最后一个token的范围，元素之间以最外层的','分隔
+    fn item_spans(&self, start: Position, end: Position) -> Vec<Span> {
+        let mut ret: Vec<Span> = vec![];
+        let mut depth = 0;
+        let mut new_item = true;
+        for token in &self.tokens[self.token_at(start.offset)..] {
+            if token.span.start.offset >= end.offset {
+                break;
+            }
+            match token.token {
+                Token::Comma if depth == 0 => {
+                    new_item = true;
+                    continue;
+                }
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                _ => {}
+            }
+            match ret.last_mut() {
+                Some(span) if !new_item => span.end = token.span.end,
+                _ => ret.push(token.span),
+            }
+            new_item = false;
+        }
+        ret
+    }
+
+    // 列表的括号之间、元素之外是否有还没有输出的注释
+    fn has_gap_comments(&self, start: Position, end: Position, spans: &[Span]) -> bool {
+        let mut from = start.offset;
+        for span in spans {
+            if self.next_comment(from, span.start.offset).is_some() {
+                return true;
+            }
+            from = span.end.offset;
+        }
+        self.next_comment(from, end.offset).is_some()
+    }
+
+    // [from, to)中第一个还没有输出的注释
+    fn next_comment(&self, from: usize, to: usize) -> Option<usize> {
+        self.comments.iter().enumerate().position(|(i, comment)| {
+            let offset = comment.span.start.offset;
+            !self.emitted[i] && offset >= from && offset < to
+        })
+    }
+
+    // 取出[from, to)中所有还没有输出的注释
+    fn take_comments(&mut self, from: usize, to: usize) -> Vec<usize> {
+        let mut ret = vec![];
+        while let Some(c) = self.next_comment(from, to) {
+            self.emitted[c] = true;
+            ret.push(c);
+        }
+        ret
+    }
+
+    // 单独占一行的注释
+    fn comment_line(
+        &mut self,
+        out: &mut String,
+        c: usize,
+        level: usize,
+        last_line: &mut Option<usize>,
+    ) {
+        let comment = &self.comments[c];
+        blank_line(out, *last_line, comment.span.start.line);
+        out.push_str(&indent(level));
+        out.push_str(comment_text(comment));
+        out.push('\n');
+        *last_line = Some(comment.span.end.line);
+        self.emitted[c] = true;
+    }
+}
+
+// 源码中与上一项之间有空行时，保留一个空行
+fn blank_line(out: &mut String, last_line: Option<usize>, line: usize) {
+    if last_line.is_some_and(|last| line > last + 1) {
+        out.push('\n');
+    }
+}
+
+fn comment_text(comment: &Comment) -> &str {
+    comment.text.trim_end()
+}
+
+fn is_if_statement(st: &Statement) -> bool {
+    matches!(&st.kind, StatementKind::ExpressionStatement(expr)
+        if matches!(expr.kind, ExpressionKind::IfExpression(..)))
+}
+
+// 表达式本身的优先级，字面量、if和函数这样自成一体的表达式最高
+fn binding_precedence(expr: &Expression) -> Precedence {
+    match &expr.kind {
+        ExpressionKind::InfixExpression(_, operator, _) => Precedence::from_token(operator),
+        ExpressionKind::AssignExpression(..) => Precedence::Assign,
+        ExpressionKind::PrefixExpression(..) => Precedence::Prefix,
+        ExpressionKind::CallExpression(..) => Precedence::Call,
+        _ => Precedence::Index,
+    }
+}
+
+fn indent(level: usize) -> String {
+    INDENT.repeat(level)
+}
+
+// text从column列开始输出时，最后一行结束的列
+fn end_column(column: usize, text: &str) -> usize {
+    match text.rfind('\n') {
+        Some(i) => text[i + 1..].chars().count(),
+        None => column + text.chars().count(),
+    }
+}
+
+// 从column列开始输出text时，每一行都不超过MAX_WIDTH。
+// 列表之后通常还跟着`;`、`,`或者`)`，最后一行留出一列
+fn fits(column: usize, text: &str) -> bool {
+    let count = text.lines().count();
+    text.lines().enumerate().all(|(i, line)| {
+        let start = if i == 0 { column } else { 0 };
+        let end = if i + 1 == count { 1 } else { 0 };
+        start + line.chars().count() + end <= MAX_WIDTH
+    })
+}
@@ -1,57 +1,42 @@
+use crate::error::{Error, ErrorKind, Result};
 use crate::lexer::lexer::Lexer;
-use crate::lexer::token::{Token, EOF_TOKEN};
-use crate::parser::program::{Expression, Ident, Precedence, Program, Statement};
-use std::fmt::{Debug, Display, Formatter};
-
+use crate::lexer::span::Span;
+use crate::lexer::token::{SpannedToken, TemplatePart, Token, EOF_TOKEN};
+use crate::parser::program::{
+    Expression, ExpressionKind, Ident, Parameter, Precedence, Program, Statement, StatementKind,
+    TemplateSegment,
+};
+use std::collections::HashMap;
+
+pub mod diagnostic;
+pub mod format;
 pub mod program;
 
 #[cfg(test)]
 mod test;
 
-pub type Result<T> = std::result::Result<T, ParseError>;
-
 pub struct Parser {
     l: Lexer,
     cur_token: Token,
+    cur_span: Span,
     peek_token: Token,
+    peek_span: Span,
+    // 当前token所在的'{'嵌套层数，用于出错后的同步
+    depth: usize,
+    // 每一层块中声明的名字以及是否是const，用于在解析时发现对const的赋值
+    scopes: Vec<HashMap<String, bool>>,
 }
 
-pub struct ParseError {
-    info: String,
-}
-
-impl From<&str> for ParseError {
-    fn from(s: &str) -> Self {
-        ParseError { info: s.to_owned() }
-    }
-}
-
-impl From<String> for ParseError {
-    fn from(s: String) -> Self {
-        ParseError { info: s }
-    }
-}
-
-impl Debug for ParseError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.info)
-    }
-}
-
-impl Display for ParseError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.info)
-    }
-}
-
-impl std::error::Error for ParseError {}
-
 impl Parser {
     pub fn new(l: Lexer) -> Self {
         let mut ret = Parser {
             l,
             cur_token: EOF_TOKEN,
+            cur_span: Span::default(),
             peek_token: EOF_TOKEN,
+            peek_span: Span::default(),
+            depth: 0,
+            scopes: vec![HashMap::new()],
         };
         ret.next_token();
         ret.next_token();
@@ -60,14 +45,21 @@ impl Parser {
     }
 
     pub fn next_token(&mut self) {
-        std::mem::swap(&mut self.cur_token, &mut self.peek_token);
-        self.peek_token = self.l.next_token();
+        let SpannedToken { token, span } = self.l.next_token();
+        self.cur_token = std::mem::replace(&mut self.peek_token, token);
+        self.cur_span = std::mem::replace(&mut self.peek_span, span);
+
+        match self.cur_token {
+            Token::LBrace => self.depth += 1,
+            Token::RBrace => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
     }
 
-    pub fn expect_peek(&mut self, token: Token) -> bool {
+    pub fn expect_peek(&mut self, token: Token, info: &str) -> Result<()> {
         if self.peek_token == token {
             self.next_token();
-            true
+            Ok(())
         } else {
             log::debug!(
                 "{}:{} parser error: expect next token to be {:?}, got {:?} instead",
@@ -76,19 +68,30 @@ impl Parser {
                 token,
                 self.peek_token
             );
-            false
+            Err(Error::unexpected(info, token, self.peek_token.clone()).or_span(self.peek_span))
         }
     }
 
+    fn error<T: Into<String>>(&self, info: T) -> Error {
+        Error::new(info).or_span(self.cur_span)
+    }
+
+    // 从start开始，到当前token为止的区间
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.cur_span)
+    }
+
+    /// 解析整个程序，遇到第一个错误即返回
     pub fn parse_program(&mut self) -> Result<Program> {
         let mut ret = Program::default();
         loop {
-            // println!("[parse loop] current token is {:?}", self.cur_token);
             if self.cur_token.is_eof() {
                 break;
             }
 
-            let statement = self.parse_statement()?;
+            let statement = self
+                .parse_statement()
+                .map_err(|e| e.or_file(self.l.file()))?;
             ret.statements.push(statement);
 
             self.next_token();
@@ -96,34 +99,119 @@ impl Parser {
         Ok(ret)
     }
 
+    /// 解析整个程序，出错后跳到下一个`;`或`}`继续解析，
+    /// 返回由所有正确语句组成的Program以及收集到的全部错误
+    pub fn parse_program_recovering(&mut self) -> (Program, Vec<Error>) {
+        let mut ret = Program::default();
+        let mut errors = vec![];
+        loop {
+            if self.cur_token.is_eof() {
+                break;
+            }
+
+            match self.parse_statement() {
+                Ok(statement) => {
+                    ret.statements.push(statement);
+                    self.next_token();
+                }
+                Err(e) => {
+                    errors.push(e.or_file(self.l.file()));
+                    self.synchronize();
+                }
+            }
+        }
+        (ret, errors)
+    }
+
+    // 跳过出错的语句：出错位置可能在某个块中，需要一直跳到回到顶层的';'或'}'
+    fn synchronize(&mut self) {
+        loop {
+            match self.cur_token {
+                Token::EOF => return,
+                Token::Semicolon | Token::RBrace if self.depth == 0 => break,
+                _ => self.next_token(),
+            }
+        }
+
+        let closed_block = self.cur_token == Token::RBrace;
+        self.next_token();
+        if closed_block && self.cur_token == Token::Semicolon {
+            self.next_token();
+        }
+    }
+
     fn parse_statement(&mut self) -> Result<Statement> {
-        match self.cur_token {
-            Token::Let => self.parse_let_statement(),
+        let start = self.cur_span;
+        let kind = match self.cur_token {
+            Token::Let | Token::Const => self.parse_let_statement(),
+            Token::Function if matches!(self.peek_token, Token::Ident(_)) => {
+                self.parse_function_statement()
+            }
             Token::Return => self.parse_return_statement(),
+            Token::While => self.parse_while_statement(),
+            Token::For => self.parse_for_statement(),
+            Token::Break => Ok(self.parse_jump_statement(StatementKind::BreakStatement)),
+            Token::Continue => Ok(self.parse_jump_statement(StatementKind::ContinueStatement)),
+            Token::Throw => self.parse_throw_statement(),
+            Token::Try => self.parse_try_statement(),
             _ => self.parse_expression_statement(),
-        }
+        }?;
+        Ok(Statement::new(kind, self.span_from(start)))
     }
 
-    fn parse_let_statement(&mut self) -> Result<Statement> {
+    // let和const语句
+    fn parse_let_statement(&mut self) -> Result<StatementKind> {
+        let is_const = self.cur_token == Token::Const;
         if let Token::Ident(_) = &self.peek_token {
             self.next_token();
+        } else {
+            return Err(
+                Error::new(format!("identifier expected after '{}'.", self.cur_token))
+                    .or_span(self.peek_span),
+            );
         }
         let identifier = self.parse_identifier()?;
-
-        if !self.expect_peek(Token::Assign) {
-            return Err("no equal sign!".into());
+        if self.declared_in_current_scope(&identifier.0) == Some(true) {
+            return Err(self.error(format!("cannot redeclare constant: {}", identifier.0)));
         }
 
+        self.expect_peek(Token::Assign, "no equal sign!")?;
+
         self.next_token();
 
         let value = self.parse_expression(Precedence::Lowest)?;
         if self.peek_token == Token::Semicolon {
             self.next_token();
         }
-        Ok(Statement::LetStatement(identifier, value))
+        self.declare(&identifier, is_const);
+        if is_const {
+            Ok(StatementKind::ConstStatement(identifier, value))
+        } else {
+            Ok(StatementKind::LetStatement(identifier, value))
+        }
+    }
+
+    fn declare(&mut self, ident: &Ident, is_const: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(ident.0.clone(), is_const);
+        }
     }
 
-    fn parse_return_statement(&mut self) -> Result<Statement> {
+    fn declared_in_current_scope(&self, name: &str) -> Option<bool> {
+        self.scopes
+            .last()
+            .and_then(|scope| scope.get(name).copied())
+    }
+
+    // 从内到外查找name，找到时返回它是否是const
+    fn resolve(&self, name: &str) -> Option<bool> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn parse_return_statement(&mut self) -> Result<StatementKind> {
         self.next_token();
 
         let ret = self.parse_expression(Precedence::Lowest)?;
@@ -131,42 +219,141 @@ impl Parser {
             self.next_token();
         }
 
-        Ok(Statement::ReturnStatement(ret))
+        Ok(StatementKind::ReturnStatement(ret))
+    }
+
+    fn parse_while_statement(&mut self) -> Result<StatementKind> {
+        self.expect_peek(Token::LParen, "'(' expected after 'while'.")?;
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+
+        self.expect_peek(
+            Token::RParen,
+            "')' expected after while condition expression",
+        )?;
+        self.expect_peek(Token::LBrace, "'{' expected for block.")?;
+
+        let body = self.parse_block_statement()?;
+        self.skip_optional_semicolon();
+        Ok(StatementKind::WhileStatement(condition, body))
+    }
+
+    fn parse_for_statement(&mut self) -> Result<StatementKind> {
+        self.expect_peek(Token::LParen, "'(' expected after 'for'.")?;
+        if let Token::Ident(_) = &self.peek_token {
+            self.next_token();
+        } else {
+            return Err(Error::new("identifier expected after 'for ('.").or_span(self.peek_span));
+        }
+        let ident = self.parse_identifier()?;
+
+        self.expect_peek(Token::In, "'in' expected after for loop variable.")?;
+        self.next_token();
+        let iterable = self.parse_expression(Precedence::Lowest)?;
+
+        self.expect_peek(Token::RParen, "')' expected after for iterable expression")?;
+        self.expect_peek(Token::LBrace, "'{' expected for block.")?;
+
+        let body = self.parse_block_with_bindings(std::slice::from_ref(&ident))?;
+        self.skip_optional_semicolon();
+        Ok(StatementKind::ForStatement(ident, iterable, body))
+    }
+
+    fn parse_throw_statement(&mut self) -> Result<StatementKind> {
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+        self.skip_optional_semicolon();
+        Ok(StatementKind::ThrowStatement(value))
+    }
+
+    fn parse_try_statement(&mut self) -> Result<StatementKind> {
+        self.expect_peek(Token::LBrace, "'{' expected after 'try'.")?;
+        let body = self.parse_block_statement()?;
+
+        let mut catch = None;
+        if self.peek_token == Token::Catch {
+            self.next_token();
+            self.expect_peek(Token::LParen, "'(' expected after 'catch'.")?;
+            if let Token::Ident(_) = &self.peek_token {
+                self.next_token();
+            } else {
+                return Err(
+                    Error::new("identifier expected after 'catch ('.").or_span(self.peek_span)
+                );
+            }
+            let ident = self.parse_identifier()?;
+            self.expect_peek(Token::RParen, "')' expected after catch variable")?;
+            self.expect_peek(Token::LBrace, "'{' expected for block.")?;
+            let handler = self.parse_block_with_bindings(std::slice::from_ref(&ident))?;
+            catch = Some((ident, handler));
+        }
+
+        let mut finally = None;
+        if self.peek_token == Token::Finally {
+            self.next_token();
+            self.expect_peek(Token::LBrace, "'{' expected after 'finally'.")?;
+            finally = Some(self.parse_block_statement()?);
+        }
+
+        if catch.is_none() && finally.is_none() {
+            return Err(Error::unexpected(
+                "'catch' or 'finally' expected after try block.",
+                Token::Catch,
+                self.peek_token.clone(),
+            )
+            .or_span(self.peek_span));
+        }
+        self.skip_optional_semicolon();
+        Ok(StatementKind::TryStatement(body, catch, finally))
+    }
+
+    // break和continue
+    fn parse_jump_statement(&mut self, kind: StatementKind) -> StatementKind {
+        self.skip_optional_semicolon();
+        kind
+    }
+
+    fn skip_optional_semicolon(&mut self) {
+        if self.peek_token == Token::Semicolon {
+            self.next_token();
+        }
     }
 
-    fn parse_expression_statement(&mut self) -> Result<Statement> {
+    fn parse_expression_statement(&mut self) -> Result<StatementKind> {
         let ret = self.parse_expression(Precedence::Lowest)?;
+
         if self.peek_token == Token::Semicolon {
             self.next_token();
         }
 
-        Ok(Statement::ExpressionStatement(ret))
+        Ok(StatementKind::ExpressionStatement(ret))
     }
 
     fn parse_identifier(&mut self) -> Result<Ident> {
         match &self.cur_token {
             Token::Ident(v) => Ok(Ident(v.clone())),
-            _ => Err("not a ident token".into()),
+            _ => Err(self.error("not a ident token")),
         }
     }
 
     fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression> {
+        let start = self.cur_span;
         // prefix
-        let mut left = match &self.cur_token {
+        let kind = match &self.cur_token {
             Token::Ident(_) => {
                 let ident = self.parse_identifier()?;
-                Ok(Expression::Identifier(ident))
+                Ok(ExpressionKind::Identifier(ident))
             }
             Token::Int(_) => self.parse_int_literal(),
+            Token::Float(_) => self.parse_float_literal(),
             Token::Bool(_) => self.parse_bool_literal(),
             Token::String(_) => self.parse_string_literal(),
+            Token::Template(_) => self.parse_template_literal(),
+            Token::Null => Ok(ExpressionKind::NullLiteral),
             Token::Bang | Token::Minus => {
                 if precedence > Precedence::Prefix {
-                    Err(format!(
-                        "'(' expected after prefix '{}'",
-                        &self.cur_token.to_string()
-                    )
-                    .into())
+                    Err(self.error(format!("'(' expected after prefix '{}'", &self.cur_token)))
                 } else {
                     self.parse_prefix_expression()
                 }
@@ -176,10 +363,16 @@ impl Parser {
             Token::Function => self.parse_function_literal(),
             Token::LBracket => self.parse_array_literal(),
             Token::LBrace => self.parse_hash_literal(),
-            _ => Err(format!("no prefix parse function for {:?}", self.cur_token)
-                .as_str()
-                .into()),
+            Token::Illegal(reason) => Err(self
+                .error(reason.as_str())
+                .with_kind(ErrorKind::IllegalToken)
+                .with_found(self.cur_token.clone())),
+            _ => Err(self
+                .error(format!("no prefix parse function for {:?}", self.cur_token))
+                .with_kind(ErrorKind::NoPrefixParse)
+                .with_found(self.cur_token.clone())),
         }?;
+        let mut left = Expression::new(kind, self.span_from(start));
 
         // infix
         loop {
@@ -190,49 +383,104 @@ impl Parser {
             }
 
             self.next_token();
-            match self.cur_token {
+            let kind = match self.cur_token {
                 Token::Eq
                 | Token::NotEq
                 | Token::LT
                 | Token::GT
+                | Token::LTE
+                | Token::GTE
+                | Token::And
+                | Token::Or
                 | Token::Plus
                 | Token::Minus
                 | Token::Slash
-                | Token::Asterisk => left = self.parse_infix_expression(left)?,
-                Token::LParen => left = self.parse_call_expression(left)?,
-                Token::LBracket => left = self.parse_index_expression(left)?,
+                | Token::Asterisk
+                | Token::Percent
+                | Token::TildeSlash
+                | Token::DoubleAsterisk
+                | Token::DotDot => self.parse_infix_expression(left)?,
+                Token::Assign
+                | Token::PlusAssign
+                | Token::MinusAssign
+                | Token::AsteriskAssign
+                | Token::SlashAssign => self.parse_assign_expression(left)?,
+                Token::LParen => self.parse_call_expression(left)?,
+                Token::LBracket => self.parse_index_expression(left)?,
                 _ => return Ok(left),
             };
+            left = Expression::new(kind, self.span_from(start));
         }
 
         Ok(left)
     }
 
-    fn parse_int_literal(&self) -> Result<Expression> {
+    fn parse_int_literal(&self) -> Result<ExpressionKind> {
         if let Token::Int(v) = self.cur_token {
-            Ok(Expression::IntLiteral(v))
+            Ok(ExpressionKind::IntLiteral(v))
+        } else {
+            Err(self.error("Token::Int not found"))
+        }
+    }
+
+    fn parse_float_literal(&self) -> Result<ExpressionKind> {
+        if let Token::Float(v) = self.cur_token {
+            Ok(ExpressionKind::FloatLiteral(v))
         } else {
-            Err("Token::Int not found".into())
+            Err(self.error("Token::Float not found"))
         }
     }
 
-    fn parse_bool_literal(&self) -> Result<Expression> {
+    fn parse_bool_literal(&self) -> Result<ExpressionKind> {
         if let Token::Bool(v) = self.cur_token {
-            Ok(Expression::BoolLiteral(v))
+            Ok(ExpressionKind::BoolLiteral(v))
         } else {
-            Err("Token::Bool not found".into())
+            Err(self.error("Token::Bool not found"))
         }
     }
 
-    fn parse_string_literal(&self) -> Result<Expression> {
+    fn parse_string_literal(&self) -> Result<ExpressionKind> {
         if let Token::String(v) = &self.cur_token {
-            Ok(Expression::StringLiteral(v.clone()))
+            Ok(ExpressionKind::StringLiteral(v.clone()))
         } else {
-            Err("Token::String not found".into())
+            Err(self.error("Token::String not found"))
+        }
+    }
+
+    // `${...}`中的代码用一个只读取这段源码的Parser解析，其中必须恰好是一个表达式
+    fn parse_template_literal(&mut self) -> Result<ExpressionKind> {
+        let parts = match &self.cur_token {
+            Token::Template(parts) => parts.clone(),
+            _ => return Err(self.error("Token::Template not found")),
+        };
+        let mut ret = vec![];
+        for part in parts {
+            match part {
+                TemplatePart::Text(text) => ret.push(TemplateSegment::Text(text)),
+                TemplatePart::Code(span) => {
+                    let mut parser = Parser::new(self.l.sub_lexer(span));
+                    if parser.cur_token.is_eof() {
+                        return Err(
+                            Error::new("empty expression in template literal").or_span(span)
+                        );
+                    }
+                    let expr = parser.parse_expression(Precedence::Lowest)?;
+                    if !parser.peek_token.is_eof() {
+                        return Err(Error::unexpected(
+                            "'}' expected after template expression.",
+                            Token::RBrace,
+                            parser.peek_token.clone(),
+                        )
+                        .or_span(parser.peek_span));
+                    }
+                    ret.push(TemplateSegment::Expression(expr));
+                }
+            }
         }
+        Ok(ExpressionKind::TemplateLiteral(ret))
     }
 
-    fn parse_prefix_expression(&mut self) -> Result<Expression> {
+    fn parse_prefix_expression(&mut self) -> Result<ExpressionKind> {
         let token = self.cur_token.clone();
         let precedence = match &token {
             Token::Minus => Precedence::Prefix.add(1),
@@ -241,66 +489,125 @@ impl Parser {
         self.next_token();
 
         let right = self.parse_expression(precedence)?;
-        Ok(Expression::PrefixExpression(token, Box::new(right)))
+        Ok(ExpressionKind::PrefixExpression(token, Box::new(right)))
     }
 
-    fn parse_infix_expression(&mut self, left: Expression) -> Result<Expression> {
+    fn parse_infix_expression(&mut self, left: Expression) -> Result<ExpressionKind> {
         let precedence = Precedence::from_token(&self.cur_token);
         let token = self.cur_token.clone();
         self.next_token();
 
         let right = match &token {
-            // to make '+' right-associate
-            // Token::Plus => self.parse_expression(precedence.sub(1))?,
+            // '**'是右结合的
+            Token::DoubleAsterisk => self.parse_expression(precedence.sub(1))?,
             _ => self.parse_expression(precedence)?,
         };
-        Ok(Expression::InfixExpression(
+        Ok(ExpressionKind::InfixExpression(
             Box::new(left),
             token,
             Box::new(right),
         ))
     }
 
-    fn parse_grouped_expression(&mut self) -> Result<Expression> {
+    // 赋值是右结合的，`a = b = 1`等价于`a = (b = 1)`
+    fn parse_assign_expression(&mut self, target: Expression) -> Result<ExpressionKind> {
+        match &target.kind {
+            ExpressionKind::Identifier(_) | ExpressionKind::IndexExpression(_, _) => {}
+            _ => {
+                return Err(Error::new(format!("invalid assignment target: {}", target))
+                    .or_span(target.span))
+            }
+        }
+        // `c = 1`和`c[0] = 1`都会修改c的绑定
+        let mut root = &target;
+        while let ExpressionKind::IndexExpression(left, _) = &root.kind {
+            root = left;
+        }
+        if let ExpressionKind::Identifier(ident) = &root.kind {
+            if self.resolve(&ident.0) == Some(true) {
+                return Err(
+                    Error::new(format!("cannot assign to constant: {}", ident.0))
+                        .or_span(target.span)
+                        .with_help("declared with `const`; use `let` for bindings that change"),
+                );
+            }
+        }
+        let token = self.cur_token.clone();
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Assign.sub(1))?;
+        Ok(ExpressionKind::AssignExpression(
+            Box::new(target),
+            token,
+            Box::new(value),
+        ))
+    }
+
+    fn parse_grouped_expression(&mut self) -> Result<ExpressionKind> {
         self.next_token();
 
         let exp = self.parse_expression(Precedence::Lowest)?;
 
-        if !self.expect_peek(Token::RParen) {
-            return Err("Right parentheses expected".into());
-        }
+        self.expect_peek(Token::RParen, "Right parentheses expected")?;
 
-        return Ok(exp);
+        Ok(exp.kind)
     }
 
-    fn parse_if_expression(&mut self) -> Result<Expression> {
-        if !self.expect_peek(Token::LParen) {
-            return Err("'(' expected after 'if'.".into());
-        }
+    fn parse_if_expression(&mut self) -> Result<ExpressionKind> {
+        self.expect_peek(Token::LParen, "'(' expected after 'if'.")?;
         self.next_token();
         let condition = self.parse_expression(Precedence::Lowest)?;
 
-        if !self.expect_peek(Token::RParen) {
-            return Err("')' expected after if condition expression".into());
-        }
+        self.expect_peek(Token::RParen, "')' expected after if condition expression")?;
 
-        if !self.expect_peek(Token::LBrace) {
-            return Err("'{' expected for block.".into());
-        }
+        self.expect_peek(Token::LBrace, "'{' expected for block.")?;
 
         let consequence = self.parse_block_statement()?;
 
-        let alternative = match self.peek_token {
-            _ => vec![],
+        let alternative = if self.peek_token == Token::Else {
+            self.next_token();
+            if self.peek_token == Token::If {
+                // else if (...) {...} 当作只包含一个if表达式的else块
+                self.next_token();
+                let start = self.cur_span;
+                let kind = self.parse_if_expression()?;
+                let expr = Expression::new(kind, self.span_from(start));
+                vec![Statement::new(
+                    StatementKind::ExpressionStatement(expr),
+                    self.span_from(start),
+                )]
+            } else {
+                self.expect_peek(Token::LBrace, "'{' expected after 'else'.")?;
+                self.parse_block_statement()?
+            }
+        } else {
+            vec![]
         };
-        return Ok(Expression::IfExpression(
+        Ok(ExpressionKind::IfExpression(
             Box::new(condition),
             consequence,
             alternative,
-        ));
+        ))
     }
 
     fn parse_block_statement(&mut self) -> Result<Vec<Statement>> {
+        self.parse_block_with_bindings(&[])
+    }
+
+    // 块是一个新的作用域，bindings是函数参数或循环变量这样预先绑定在块中的名字
+    fn parse_block_with_bindings(&mut self, bindings: &[Ident]) -> Result<Vec<Statement>> {
+        self.scopes.push(
+            bindings
+                .iter()
+                .map(|ident| (ident.0.clone(), false))
+                .collect(),
+        );
+        let ret = self.parse_block_body();
+        self.scopes.pop();
+        ret
+    }
+
+    fn parse_block_body(&mut self) -> Result<Vec<Statement>> {
         self.next_token(); // LBrace
 
         let mut ret = vec![];
@@ -313,63 +620,82 @@ impl Parser {
         Ok(ret)
     }
 
-    fn parse_function_literal(&mut self) -> Result<Expression> {
-        // TODO! 支持function名称
-        if !self.expect_peek(Token::LParen) {
-            return Err("'(' expected for function expression".into());
+    fn parse_function_literal(&mut self) -> Result<ExpressionKind> {
+        let (params, sts) = self.parse_function_body()?;
+        Ok(ExpressionKind::FunctionExpression(
+            params.into(),
+            sts.into(),
+        ))
+    }
+
+    fn parse_function_statement(&mut self) -> Result<StatementKind> {
+        self.next_token();
+        let name = self.parse_identifier()?;
+        if self.declared_in_current_scope(&name.0) == Some(true) {
+            return Err(self.error(format!("cannot redeclare constant: {}", name.0)));
         }
+        // 先声明名字，函数体中可以递归调用
+        self.declare(&name, false);
+
+        let (params, body) = self.parse_function_body()?;
+        self.skip_optional_semicolon();
+        Ok(StatementKind::FunctionStatement(
+            name,
+            params.into(),
+            body.into(),
+        ))
+    }
+
+    // 从'('开始的参数列表和函数体
+    fn parse_function_body(&mut self) -> Result<(Vec<Parameter>, Vec<Statement>)> {
+        self.expect_peek(Token::LParen, "'(' expected for function expression")?;
 
         let params = self.parse_function_parameters()?;
 
-        if !self.expect_peek(Token::LBrace) {
-            return Err("'{' expected for function body.".into());
-        }
+        self.expect_peek(Token::LBrace, "'{' expected for function body.")?;
 
-        let sts = self.parse_block_statement()?;
-        Ok(Expression::FunctionExpression(params, sts))
+        let names = params
+            .iter()
+            .map(|param| param.name.clone())
+            .collect::<Vec<Ident>>();
+        let sts = self.parse_block_with_bindings(&names)?;
+        Ok((params, sts))
     }
 
-    fn parse_array_literal(&mut self) -> Result<Expression> {
+    fn parse_array_literal(&mut self) -> Result<ExpressionKind> {
         let elements = self.parse_expression_list(&Token::RBracket)?;
 
-        if !self.expect_peek(Token::RBracket) {
-            return Err("']' expected for array definition.".into());
-        }
+        self.expect_peek(Token::RBracket, "']' expected for array definition.")?;
 
-        Ok(Expression::ArrayLiteral(elements))
+        Ok(ExpressionKind::ArrayLiteral(elements))
     }
 
-    fn parse_hash_literal(&mut self) -> Result<Expression> {
+    fn parse_hash_literal(&mut self) -> Result<ExpressionKind> {
         let mut ret: Vec<(Expression, Expression)> = Default::default();
         while self.peek_token != Token::RBrace {
             self.next_token();
             let key = self.parse_expression(Precedence::Lowest)?;
 
-            if !self.expect_peek(Token::Colon) {
-                return Err("':' expected in Hash element.".into());
-            }
+            self.expect_peek(Token::Colon, "':' expected in Hash element.")?;
 
             self.next_token();
             let value = self.parse_expression(Precedence::Lowest)?;
             ret.push((key, value));
 
-            if self.peek_token != Token::RBrace && self.peek_token != Token::Comma {
-                return Err("'}' or ',' expected in Hash element.".into());
+            if self.peek_token != Token::RBrace {
+                self.expect_peek(Token::Comma, "'}' or ',' expected in Hash element.")?;
             }
         }
 
-        if !self.expect_peek(Token::RBrace) {
-            return Err("'}' expected for Hash end.".into());
-        }
+        self.expect_peek(Token::RBrace, "'}' expected for Hash end.")?;
 
-        Ok(Expression::HashLiteral(ret))
+        Ok(ExpressionKind::HashLiteral(ret))
     }
-
     fn parse_expression_list(&mut self, end: &Token) -> Result<Vec<Expression>> {
         let mut ret = vec![];
 
+        // 空列表，结束符留给调用方检查
         if self.peek_token.eq(end) {
-            self.next_token();
             return Ok(ret);
         }
 
@@ -385,59 +711,147 @@ impl Parser {
         Ok(ret)
     }
 
-    fn parse_function_parameters(&mut self) -> Result<Vec<Ident>> {
-        let mut ret = vec![];
-        self.next_token();
+    fn parse_function_parameters(&mut self) -> Result<Vec<Parameter>> {
+        let mut ret: Vec<Parameter> = vec![];
 
         // 没有参数的情况
-        if self.cur_token == Token::RParen {
+        if self.peek_token == Token::RParen {
+            self.next_token();
             return Ok(ret);
         }
 
         loop {
-            if let Token::Ident(v) = &self.cur_token {
-                ret.push(Ident(v.clone()));
+            self.next_token();
+            let rest = self.cur_token == Token::Ellipsis;
+            if rest {
+                self.next_token();
+            }
+            let name = match &self.cur_token {
+                Token::Ident(v) => Ident(v.clone()),
+                _ => {
+                    return Err(self
+                        .error("parameter name expected.")
+                        .with_found(self.cur_token.clone()))
+                }
+            };
+            if ret.iter().any(|param| param.name == name) {
+                return Err(self.error(format!("duplicate parameter: {}", name.0)));
             }
 
+            let mut param = Parameter::new(name);
+            param.rest = rest;
+            if self.peek_token == Token::Assign {
+                if rest {
+                    return Err(Error::new("rest parameter cannot have a default value.")
+                        .or_span(self.peek_span));
+                }
+                self.next_token();
+                self.next_token();
+                param.default = Some(self.parse_expression(Precedence::Lowest)?);
+            }
+            ret.push(param);
+
             if self.peek_token != Token::Comma {
                 break;
             }
-
+            if rest {
+                return Err(Error::new("rest parameter must be the last parameter.")
+                    .or_span(self.peek_span));
+            }
             self.next_token(); // comma
-            self.next_token(); // next ident
         }
 
-        if !self.expect_peek(Token::RParen) {
-            return Err("')' expected for function parameters expression.".into());
-        }
+        self.expect_peek(
+            Token::RParen,
+            "')' expected for function parameters expression.",
+        )?;
 
         Ok(ret)
     }
 
-    fn parse_call_expression(&mut self, function: Expression) -> Result<Expression> {
-        Ok(Expression::CallExpression(
+    fn parse_call_expression(&mut self, function: Expression) -> Result<ExpressionKind> {
+        Ok(ExpressionKind::CallExpression(
             Box::new(function),
             self.parse_call_arguments()?,
         ))
     }
 
+    // 命名参数必须出现在所有位置参数（包括展开的参数）之后
     fn parse_call_arguments(&mut self) -> Result<Vec<Expression>> {
-        let ret = self.parse_expression_list(&Token::RParen)?;
+        let mut ret: Vec<Expression> = vec![];
 
-        if !self.expect_peek(Token::RParen) {
-            return Err("')' expected for function call.".into());
+        if self.peek_token == Token::RParen {
+            self.next_token();
+            return Ok(ret);
         }
 
+        loop {
+            self.next_token();
+            let arg = self.parse_call_argument()?;
+            match &arg.kind {
+                ExpressionKind::NamedArgument(name, _) => {
+                    let duplicated = ret.iter().any(|other| {
+                        matches!(&other.kind, ExpressionKind::NamedArgument(n, _) if n == name)
+                    });
+                    if duplicated {
+                        return Err(
+                            Error::new(format!("duplicate argument: {}", name.0)).or_span(arg.span)
+                        );
+                    }
+                }
+                _ => {
+                    let after_named = ret
+                        .iter()
+                        .any(|other| matches!(other.kind, ExpressionKind::NamedArgument(_, _)));
+                    if after_named {
+                        return Err(Error::new(
+                            "positional argument cannot follow named arguments.",
+                        )
+                        .or_span(arg.span));
+                    }
+                }
+            }
+            ret.push(arg);
+
+            if self.peek_token != Token::Comma {
+                break;
+            }
+            self.next_token(); // comma
+        }
+
+        self.expect_peek(Token::RParen, "')' expected for function call.")?;
+
         Ok(ret)
     }
 
-    fn parse_index_expression(&mut self, left: Expression) -> Result<Expression> {
+    fn parse_call_argument(&mut self) -> Result<Expression> {
+        let start = self.cur_span;
+        let kind = match (&self.cur_token, &self.peek_token) {
+            (Token::Ellipsis, _) => {
+                self.next_token();
+                let expr = self.parse_expression(Precedence::Lowest)?;
+                ExpressionKind::SpreadExpression(Box::new(expr))
+            }
+            (Token::Ident(name), Token::Colon) => {
+                let name = Ident(name.clone());
+                self.next_token(); // colon
+                self.next_token();
+                let expr = self.parse_expression(Precedence::Lowest)?;
+                ExpressionKind::NamedArgument(name, Box::new(expr))
+            }
+            _ => return self.parse_expression(Precedence::Lowest),
+        };
+        Ok(Expression::new(kind, self.span_from(start)))
+    }
+
+    fn parse_index_expression(&mut self, left: Expression) -> Result<ExpressionKind> {
         self.next_token();
         let index = self.parse_expression(Precedence::Lowest)?;
 
-        if !self.expect_peek(Token::RBracket) {
-            return Err("']' expected for index end.".into());
-        }
-        Ok(Expression::IndexExpression(Box::new(left), Box::new(index)))
+        self.expect_peek(Token::RBracket, "']' expected for index end.")?;
+        Ok(ExpressionKind::IndexExpression(
+            Box::new(left),
+            Box::new(index),
+        ))
     }
 }
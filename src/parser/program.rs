@@ -1,38 +1,166 @@
+use crate::eval::quote;
+use crate::lexer::span::Span;
 use crate::lexer::token::Token;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
 
 #[derive(PartialEq, Debug, Clone, Eq)]
 pub struct Ident(pub String);
 
+/// 函数参数：`a`、带默认值的`b = 2`以及只能出现在最后的`...rest`
 #[derive(PartialEq, Debug, Clone, Eq)]
-pub enum Statement {
+pub struct Parameter {
+    pub name: Ident,
+    pub default: Option<Expression>,
+    pub rest: bool,
+}
+
+impl Parameter {
+    pub fn new(name: Ident) -> Self {
+        Parameter {
+            name,
+            default: None,
+            rest: false,
+        }
+    }
+}
+
+impl Display for Parameter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.rest {
+            f.write_str("...")?;
+        }
+        f.write_str(&self.name.0)?;
+        if let Some(default) = &self.default {
+            write!(f, " = {}", default)?;
+        }
+        Ok(())
+    }
+}
+
+/// 逗号分隔的参数列表，例如`a, b = 2, ...rest`
+pub fn join_parameters(params: &[Parameter]) -> String {
+    params
+        .iter()
+        .map(|param| param.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// 语句节点，kind是语句本身，span是它在源码中的位置
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(PartialEq, Debug, Clone, Eq)]
+pub enum StatementKind {
     LetStatement(Ident, Expression),
+    ConstStatement(Ident, Expression),
     ReturnStatement(Expression),
     ExpressionStatement(Expression),
+    WhileStatement(
+        Expression,     /* condition */
+        Vec<Statement>, /* body */
+    ),
+    ForStatement(
+        Ident,          /* variable */
+        Expression,     /* iterable */
+        Vec<Statement>, /* body */
+    ),
+    BreakStatement,
+    ContinueStatement,
+    // `fn name(a, b) { ... }`，名字在所在作用域的语句执行之前就已绑定
+    FunctionStatement(
+        Ident,            /* name */
+        Arc<[Parameter]>, /* arguments */
+        Arc<[Statement]>, /* body */
+    ),
+    ThrowStatement(Expression),
+    // `try { } catch (e) { } finally { }`，catch和finally至少有一个
+    TryStatement(
+        Vec<Statement>,                  /* body */
+        Option<(Ident, Vec<Statement>)>, /* catch */
+        Option<Vec<Statement>>,          /* finally */
+    ),
 }
 
 impl Statement {
-    pub fn to_string(&self) -> String {
-        match self {
-            Statement::LetStatement(ident, expression) => {
-                format!("let {} = {};", ident.0, expression.to_string())
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Statement { kind, span }
+    }
+}
+
+// 语法树的比较只关心结构，不关心位置
+impl PartialEq for Statement {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Eq for Statement {}
+
+impl From<StatementKind> for Statement {
+    fn from(kind: StatementKind) -> Self {
+        Statement::new(kind, Span::default())
+    }
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            StatementKind::LetStatement(ident, expression) => {
+                write!(f, "let {} = {};", ident.0, expression)
+            }
+            StatementKind::ConstStatement(ident, expression) => {
+                write!(f, "const {} = {};", ident.0, expression)
             }
-            Statement::ExpressionStatement(expr) => {
-                expr.to_string()
-                // format!("{};", expr.to_string())
+            StatementKind::ReturnStatement(expr) => write!(f, "return {};", expr),
+            StatementKind::ExpressionStatement(expr) => write!(f, "{}", expr),
+            StatementKind::WhileStatement(condition, body) => {
+                write!(f, "while ({}) {{{}}}", condition, join_statements(body))
+            }
+            StatementKind::ForStatement(ident, iterable, body) => write!(
+                f,
+                "for ({} in {}) {{{}}}",
+                ident.0,
+                iterable,
+                join_statements(body)
+            ),
+            StatementKind::BreakStatement => f.write_str("break;"),
+            StatementKind::ContinueStatement => f.write_str("continue;"),
+            StatementKind::FunctionStatement(name, params, body) => write!(
+                f,
+                "fn {}({}) {{{}}}",
+                name.0,
+                join_parameters(params),
+                join_statements(body)
+            ),
+            StatementKind::ThrowStatement(expr) => write!(f, "throw {};", expr),
+            StatementKind::TryStatement(body, catch, finally) => {
+                write!(f, "try {{{}}}", join_statements(body))?;
+                if let Some((ident, handler)) = catch {
+                    write!(f, " catch ({}) {{{}}}", ident.0, join_statements(handler))?;
+                }
+                if let Some(finally) = finally {
+                    write!(f, " finally {{{}}}", join_statements(finally))?;
+                }
+                Ok(())
             }
-            _ => "".to_string(),
         }
     }
 }
 
-// TODO 这个结构可能是多余的定义，也许可以直接使用Vec<Statement>代替，后面再确认
-#[derive(PartialEq, Debug, Clone, Eq)]
-pub struct BlockStatement {
-    statements: Vec<Statement>,
+/// 表达式节点，kind是表达式本身，span是它在源码中的位置
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
 }
 
 #[derive(PartialEq, Debug, Clone)]
-pub enum Expression {
+pub enum ExpressionKind {
     Identifier(Ident),
     IfExpression(
         Box<Expression>, /* condition */
@@ -43,14 +171,16 @@ pub enum Expression {
         Box<Expression>, /* function */
         Vec<Expression>, /* parameters */
     ),
+    // 参数和函数体是共享的，每次求值得到的闭包只增加引用计数
     FunctionExpression(
-        Vec<Ident>,     /* arguments */
-        Vec<Statement>, /* body */
+        Arc<[Parameter]>, /* arguments */
+        Arc<[Statement]>, /* body */
     ),
     IntLiteral(i64),
     FloatLiteral(f64),
     BoolLiteral(bool),
     StringLiteral(String),
+    NullLiteral,
     PrefixExpression(Token, Box<Expression>),
     InfixExpression(
         Box<Expression>, /* left */
@@ -63,56 +193,117 @@ pub enum Expression {
         Box<Expression>, /* index */
     ),
     HashLiteral(Vec<(Expression, Expression)>),
+    TemplateLiteral(Vec<TemplateSegment>),
+    // 只出现在调用参数中：`f(...arr)`和`f(b: 3)`
+    SpreadExpression(Box<Expression>),
+    NamedArgument(Ident, Box<Expression>),
+    // `x = v`、`arr[i] += v`，target只能是标识符或下标表达式
+    AssignExpression(
+        Box<Expression>, /* target */
+        Token,           /* operator */
+        Box<Expression>, /* value */
+    ),
 }
 
-impl Eq for Expression {}
+impl Eq for ExpressionKind {}
+
+/// 模板字符串中的一段：文本或者`${...}`中的表达式
+#[derive(PartialEq, Debug, Clone, Eq)]
+pub enum TemplateSegment {
+    Text(String),
+    Expression(Expression),
+}
 
 impl Expression {
-    pub fn to_string(&self) -> String {
-        match self {
-            Expression::Identifier(ident) => ident.0.clone(),
-            Expression::IntLiteral(v) => v.to_string(),
-            Expression::BoolLiteral(v) => v.to_string(),
-            Expression::PrefixExpression(prefix, right) => {
-                format!("({}{})", prefix.to_string(), right.to_string())
-            }
-            Expression::InfixExpression(left, operator, right) => {
-                format!(
-                    "({} {} {})",
-                    left.to_string(),
-                    operator.to_string(),
-                    right.to_string()
-                )
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Expression { kind, span }
+    }
+}
+
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Eq for Expression {}
+
+impl From<ExpressionKind> for Expression {
+    fn from(kind: ExpressionKind) -> Self {
+        Expression::new(kind, Span::default())
+    }
+}
+
+fn join_statements(list: &[Statement]) -> String {
+    list.iter().map(|st| st.to_string()).collect::<String>()
+}
+
+fn join(list: &[Expression]) -> String {
+    list.iter()
+        .map(|expr| expr.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ExpressionKind::Identifier(ident) => write!(f, "{}", ident.0),
+            ExpressionKind::IntLiteral(v) => write!(f, "{}", v),
+            ExpressionKind::FloatLiteral(v) => write!(f, "{:?}", v),
+            ExpressionKind::BoolLiteral(v) => write!(f, "{}", v),
+            ExpressionKind::NullLiteral => f.write_str("null"),
+            ExpressionKind::PrefixExpression(prefix, right) => write!(f, "({}{})", prefix, right),
+            ExpressionKind::InfixExpression(left, operator, right)
+            | ExpressionKind::AssignExpression(left, operator, right) => {
+                write!(f, "({} {} {})", left, operator, right)
             }
-            Expression::CallExpression(function, params) => {
-                let params_str = params
-                    .iter()
-                    .map(|expr| expr.to_string())
-                    .collect::<Vec<String>>()
-                    .join(", ");
-                format!("{}({})", function.to_string(), params_str)
+            ExpressionKind::CallExpression(function, params) => {
+                write!(f, "{}({})", function, join(params))
             }
-            Expression::StringLiteral(v) => v.clone(),
-            Expression::ArrayLiteral(array) => {
-                let array_str = array
-                    .iter()
-                    .map(|expr| expr.to_string())
-                    .collect::<Vec<String>>()
-                    .join(", ");
-                format!("[{}]", array_str)
+            ExpressionKind::IfExpression(condition, consequence, alternative) => {
+                write!(f, "if ({}) {{{}}}", condition, join_statements(consequence))?;
+                if !alternative.is_empty() {
+                    write!(f, " else {{{}}}", join_statements(alternative))?;
+                }
+                Ok(())
             }
-            Expression::IndexExpression(left, index) => {
-                format!("({}[{}])", left.to_string(), index.to_string())
+            ExpressionKind::FunctionExpression(params, body) => write!(
+                f,
+                "fn({}) {{{}}}",
+                join_parameters(params),
+                join_statements(body)
+            ),
+            ExpressionKind::StringLiteral(v) => f.write_str(&quote(v)),
+            ExpressionKind::ArrayLiteral(array) => write!(f, "[{}]", join(array)),
+            ExpressionKind::IndexExpression(left, index) => write!(f, "({}[{}])", left, index),
+            ExpressionKind::TemplateLiteral(segments) => {
+                f.write_str("`")?;
+                for segment in segments {
+                    match segment {
+                        TemplateSegment::Text(text) => {
+                            for ch in text.chars() {
+                                match ch {
+                                    '`' | '$' | '\\' => write!(f, "\\{}", ch)?,
+                                    _ => write!(f, "{}", ch)?,
+                                }
+                            }
+                        }
+                        TemplateSegment::Expression(expr) => write!(f, "${{{}}}", expr)?,
+                    }
+                }
+                f.write_str("`")
             }
-            Expression::HashLiteral(list) => {
+            ExpressionKind::SpreadExpression(expr) => write!(f, "...{}", expr),
+            ExpressionKind::NamedArgument(name, expr) => write!(f, "{}: {}", name.0, expr),
+            ExpressionKind::HashLiteral(list) => {
                 let map_str = list
                     .iter()
-                    .map(|(left, right)| format!("{}: {}", left.to_string(), right.to_string()))
+                    .map(|(left, right)| format!("{}: {}", left, right))
                     .collect::<Vec<String>>()
                     .join(", ");
-                format!("{{{}}}", map_str)
+                write!(f, "{{{}}}", map_str)
             }
-            _ => "".to_string(),
         }
     }
 }
@@ -120,27 +311,31 @@ impl Expression {
 #[derive(PartialEq, PartialOrd, Debug, Eq, Clone)]
 pub enum Precedence {
     Lowest,
+    Assign,      // = += -= *= /=
+    LogicalOr,   // ||
+    LogicalAnd,  // &&
     Equals,      // ==
-    LessGreater, // > or <
+    LessGreater, // > or < or >= or <=
+    Range,       // ..
     Sum,         // +
-    Product,     // *
+    Product,     // * / % ~/
+    Power,       // **
     Prefix,      // -x or !x
     Call,        // my_func(x)
     Index,       // array[index]
 }
 
-#[derive(Default)]
+#[derive(Default, Debug)]
 pub struct Program {
     pub(crate) statements: Vec<Statement>,
 }
 
-impl Program {
-    pub fn to_string(&self) -> String {
-        let mut ret = String::new();
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         for st in &self.statements {
-            ret.push_str(&st.to_string());
+            write!(f, "{}", st)?;
         }
-        return ret;
+        Ok(())
     }
 }
 
@@ -148,9 +343,20 @@ impl Precedence {
     pub fn from_token(token: &Token) -> Self {
         match token {
             Token::Eq | Token::NotEq => Precedence::Equals,
-            Token::LT | Token::GT => Precedence::LessGreater,
+            Token::Assign
+            | Token::PlusAssign
+            | Token::MinusAssign
+            | Token::AsteriskAssign
+            | Token::SlashAssign => Precedence::Assign,
+            Token::Or => Precedence::LogicalOr,
+            Token::And => Precedence::LogicalAnd,
+            Token::LT | Token::GT | Token::LTE | Token::GTE => Precedence::LessGreater,
+            Token::DotDot => Precedence::Range,
             Token::Plus | Token::Minus => Precedence::Sum,
-            Token::Slash | Token::Asterisk => Precedence::Product,
+            Token::Slash | Token::Asterisk | Token::Percent | Token::TildeSlash => {
+                Precedence::Product
+            }
+            Token::DoubleAsterisk => Precedence::Power,
             Token::LParen => Precedence::Call,
             Token::LBracket => Precedence::Index,
             _ => Precedence::Lowest,
@@ -161,26 +367,36 @@ impl Precedence {
     pub fn to_i32(&self) -> i32 {
         match self {
             Precedence::Lowest => 0,
-            Precedence::Equals => 1,
-            Precedence::LessGreater => 2,
-            Precedence::Sum => 3,
-            Precedence::Product => 4,
-            Precedence::Prefix => 5,
-            Precedence::Call => 6,
-            Precedence::Index => 7,
+            Precedence::Assign => 1,
+            Precedence::LogicalOr => 2,
+            Precedence::LogicalAnd => 3,
+            Precedence::Equals => 4,
+            Precedence::LessGreater => 5,
+            Precedence::Range => 6,
+            Precedence::Sum => 7,
+            Precedence::Product => 8,
+            Precedence::Power => 9,
+            Precedence::Prefix => 10,
+            Precedence::Call => 11,
+            Precedence::Index => 12,
         }
     }
 
     #[inline]
     pub fn from_i32(v: i32) -> Self {
         match v {
-            1 => Precedence::Equals,
-            2 => Precedence::LessGreater,
-            3 => Precedence::Sum,
-            4 => Precedence::Product,
-            5 => Precedence::Prefix,
-            6 => Precedence::Call,
-            7 => Precedence::Index,
+            1 => Precedence::Assign,
+            2 => Precedence::LogicalOr,
+            3 => Precedence::LogicalAnd,
+            4 => Precedence::Equals,
+            5 => Precedence::LessGreater,
+            6 => Precedence::Range,
+            7 => Precedence::Sum,
+            8 => Precedence::Product,
+            9 => Precedence::Power,
+            10 => Precedence::Prefix,
+            11 => Precedence::Call,
+            12 => Precedence::Index,
             _ => Precedence::Lowest,
         }
     }
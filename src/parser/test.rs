@@ -1,11 +1,12 @@
+use crate::error::ErrorKind;
 use crate::lexer::lexer::Lexer;
 use crate::lexer::token::Token;
-use crate::parser::program::{Expression, Ident, Statement};
-use crate::parser::Parser;
+use crate::parser::program::{Expression, ExpressionKind, Ident, Statement, StatementKind};
+use crate::parser::{diagnostic, format, Parser};
 
 #[cfg(test)]
 fn check_let_statement(st: &Statement, name_expect: &str, value_expected: &Expression) -> bool {
-    if let Statement::LetStatement(name, v) = st {
+    if let StatementKind::LetStatement(name, v) = &st.kind {
         name_expect.eq(&name.0) && v.eq(value_expected)
     } else {
         false
@@ -23,11 +24,15 @@ fn check_let_statement(st: &Statement, name_expect: &str, value_expected: &Expre
 // }
 
 #[cfg(test)]
-fn check_function_expression(st: &Statement, expects: &Vec<&str>) -> bool {
-    if let Statement::ExpressionStatement(Expression::FunctionExpression(params, _)) = st {
+fn check_function_expression(st: &Statement, expects: &[&str]) -> bool {
+    if let StatementKind::ExpressionStatement(Expression {
+        kind: ExpressionKind::FunctionExpression(params, _),
+        ..
+    }) = &st.kind
+    {
         assert_eq!(expects.len(), params.len());
         for (i, param) in params.iter().enumerate() {
-            assert_eq!(param.0, expects[i]);
+            assert_eq!(param.name.0, expects[i]);
         }
         true
     } else {
@@ -45,12 +50,15 @@ fn test_let_statements() {
     let y = true;
     let foobar = y;
     ";
-    let name_values = vec![
-        ("x", Expression::IntLiteral(5)),
-        ("y", Expression::IntLiteral(10)),
-        ("foobar", Expression::IntLiteral(838383)),
-        ("y", Expression::BoolLiteral(true)),
-        ("foobar", Expression::Identifier(Ident("y".into()))),
+    let name_values: [(&str, Expression); 5] = [
+        ("x", ExpressionKind::IntLiteral(5).into()),
+        ("y", ExpressionKind::IntLiteral(10).into()),
+        ("foobar", ExpressionKind::IntLiteral(838383).into()),
+        ("y", ExpressionKind::BoolLiteral(true).into()),
+        (
+            "foobar",
+            ExpressionKind::Identifier(Ident("y".into())).into(),
+        ),
     ];
 
     let l = Lexer::new(input);
@@ -78,15 +86,22 @@ fn test_infix_expression() {
     assert_eq!(program.statements.len(), 1);
     assert_eq!(
         program.statements[0],
-        Statement::ExpressionStatement(Expression::InfixExpression(
-            Box::new(Expression::IntLiteral(4)),
-            Token::Plus,
-            Box::new(Expression::InfixExpression(
-                Box::new(Expression::IntLiteral(5)),
+        StatementKind::ExpressionStatement(
+            ExpressionKind::InfixExpression(
+                Box::new(
+                    ExpressionKind::InfixExpression(
+                        Box::new(ExpressionKind::IntLiteral(4).into()),
+                        Token::Plus,
+                        Box::new(ExpressionKind::IntLiteral(5).into()),
+                    )
+                    .into()
+                ),
                 Token::Plus,
-                Box::new(Expression::IntLiteral(10))
-            ))
-        ))
+                Box::new(ExpressionKind::IntLiteral(10).into()),
+            )
+            .into()
+        )
+        .into()
     );
 }
 
@@ -99,10 +114,18 @@ fn test_operator_precedence() {
         ("a + b - c", "((a + b) - c)"),
         ("a * b * c", "((a * b) * c)"),
         ("a * b / c", "((a * b) / c)"),
+        ("a % b ~/ c", "((a % b) ~/ c)"),
+        ("a ** b ** c", "(a ** (b ** c))"),
+        ("a * b ** c", "(a * (b ** c))"),
+        ("-a ** b", "((-a) ** b)"),
         ("a + b / c", "(a + (b / c))"),
         ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
         ("3 + 4; -5 * 5", "(3 + 4)((-5) * 5)"),
         ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
+        ("a <= b == b >= a", "((a <= b) == (b >= a))"),
+        ("a || b && c", "(a || (b && c))"),
+        ("a && b || c && d", "((a && b) || (c && d))"),
+        ("x != null && x > 0", "((x != null) && (x > 0))"),
         ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4))"),
         (
             "3 + 4 * 5 == 3 * 1 + 4 * 5",
@@ -149,6 +172,48 @@ fn test_if_else_expression() {
     let program = p.parse_program().unwrap();
 
     assert_eq!(program.statements.len(), 1);
+    if let StatementKind::ExpressionStatement(Expression {
+        kind: ExpressionKind::IfExpression(_, consequence, alternative),
+        ..
+    }) = &program.statements[0].kind
+    {
+        assert_eq!(consequence.len(), 1);
+        assert_eq!(alternative.len(), 1);
+        assert_eq!(alternative[0].to_string(), "y");
+    } else {
+        panic!("expect an if expression, got {:?}", program.statements[0]);
+    }
+}
+
+#[test]
+fn test_else_if_expression() {
+    let input = "if (x < y) { x } else if (x > y) { y } else { 0 }; 1";
+
+    let l = Lexer::new(input);
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+
+    assert_eq!(program.statements.len(), 2);
+    if let StatementKind::ExpressionStatement(Expression {
+        kind: ExpressionKind::IfExpression(_, _, alternative),
+        ..
+    }) = &program.statements[0].kind
+    {
+        assert_eq!(alternative.len(), 1);
+        if let StatementKind::ExpressionStatement(Expression {
+            kind: ExpressionKind::IfExpression(condition, consequence, alternative),
+            ..
+        }) = &alternative[0].kind
+        {
+            assert_eq!(condition.to_string(), "(x > y)");
+            assert_eq!(consequence[0].to_string(), "y");
+            assert_eq!(alternative[0].to_string(), "0");
+        } else {
+            panic!("expect an else-if expression, got {:?}", alternative[0]);
+        }
+    } else {
+        panic!("expect an if expression, got {:?}", program.statements[0]);
+    }
 }
 
 #[test]
@@ -179,6 +244,58 @@ fn test_call_expression() {
     println!("{:?}", program.statements[0]);
 }
 
+#[test]
+fn test_empty_expression_list() {
+    let cases = [("f()", "f()"), ("[]", "[]"), ("f([])", "f([])")];
+
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse_program().unwrap();
+        assert_eq!(program.to_string(), expect);
+    }
+}
+
+#[test]
+fn test_float_literal() {
+    let input = "let x = 1.5; -2.5e2 * x;";
+
+    let l = Lexer::new(input);
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+
+    assert!(check_let_statement(
+        &program.statements[0],
+        "x",
+        &ExpressionKind::FloatLiteral(1.5).into()
+    ));
+    assert_eq!(program.statements[1].to_string(), "((-250.0) * x)");
+}
+
+#[test]
+fn test_hash_literal() {
+    let cases = [
+        ("{}", "{}"),
+        ("{\"one\": 1}", "{\"one\": 1}"),
+        (
+            "{\"one\": 0 + 1, two: 2 * 3, 3: [1, 2],}",
+            "{\"one\": (0 + 1), two: (2 * 3), 3: [1, 2]}",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse_program().unwrap();
+        assert_eq!(program.to_string(), expect);
+    }
+
+    let l = Lexer::new("{1: 2 3: 4}");
+    let mut p = Parser::new(l);
+    let err = p.parse_program().unwrap_err();
+    assert_eq!(err.to_string(), "1:7: '}' or ',' expected in Hash element.");
+}
+
 #[test]
 fn test_string_literal() {
     let input = "\"hello world\";";
@@ -188,13 +305,659 @@ fn test_string_literal() {
     let program = p.parse_program().unwrap();
 
     assert_eq!(program.statements.len(), 1);
-    if let Statement::ExpressionStatement(Expression::StringLiteral(v)) = &program.statements[0] {
+    if let StatementKind::ExpressionStatement(Expression {
+        kind: ExpressionKind::StringLiteral(v),
+        ..
+    }) = &program.statements[0].kind
+    {
         assert_eq!(v, "hello world");
     } else {
-        assert!(
-            false,
+        panic!(
             "expect a string literal, but a {:?}",
             &program.statements[0]
         );
     }
 }
+
+#[test]
+fn test_statement_span() {
+    let input = "let x = 5;\n  add(x, 1 * 2);";
+    let l = Lexer::new(input);
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+
+    let spans = program
+        .statements
+        .iter()
+        .map(|st| (st.span.start.offset, st.span.end.offset))
+        .collect::<Vec<_>>();
+    assert_eq!(spans, vec![(0, 10), (13, 27)]);
+
+    if let StatementKind::ExpressionStatement(Expression {
+        kind: ExpressionKind::CallExpression(_, args),
+        span,
+    }) = &program.statements[1].kind
+    {
+        assert_eq!((span.start.line, span.start.column), (2, 3));
+        assert_eq!((span.end.line, span.end.column), (2, 16));
+        assert_eq!(
+            (args[1].span.start.column, args[1].span.end.column),
+            (10, 15)
+        );
+    } else {
+        panic!("expect a call expression, got {:?}", program.statements[1]);
+    }
+}
+
+#[test]
+fn test_parse_error_position() {
+    let cases = [
+        ("let x 5;", "1:7: no equal sign!"),
+        (
+            "let x = 1;\nlet y = (2 + 3;",
+            "2:15: Right parentheses expected",
+        ),
+        ("\n\n  let = 1;", "3:7: identifier expected after 'let'."),
+    ];
+
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let err = p.parse_program().unwrap_err();
+        assert_eq!(err.to_string(), expect);
+    }
+
+    let l = Lexer::with_file("let x 5;", "test.mk");
+    let mut p = Parser::new(l);
+    let err = p.parse_program().unwrap_err();
+    assert_eq!(err.to_string(), "test.mk:1:7: no equal sign!");
+}
+
+#[test]
+fn test_recovering_parser() {
+    let input = r"let a = 1;
+let b = ;
+let c = (1 + 2;
+let f = fn(x) {
+    let y x;
+    x
+};
+let d = 4;
+";
+    let l = Lexer::new(input);
+    let mut p = Parser::new(l);
+    let (program, errors) = p.parse_program_recovering();
+
+    let lets = program
+        .statements
+        .iter()
+        .filter_map(|st| match &st.kind {
+            StatementKind::LetStatement(ident, _) => Some(ident.0.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lets, vec!["a", "d"]);
+
+    let diagnostics = errors
+        .iter()
+        .map(|e| {
+            let span = e.span().unwrap();
+            (e.kind(), span.start.line, span.start.column)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        diagnostics,
+        vec![
+            (ErrorKind::NoPrefixParse, 2, 9),
+            (ErrorKind::UnexpectedToken, 3, 15),
+            (ErrorKind::UnexpectedToken, 5, 11),
+        ]
+    );
+
+    assert_eq!(errors[1].expected(), Some(&Token::RParen));
+    assert_eq!(errors[1].found(), Some(&Token::Semicolon));
+    assert_eq!(errors[2].expected(), Some(&Token::Assign));
+    assert_eq!(errors[2].found(), Some(&Token::from_str("x")));
+}
+
+#[test]
+fn test_recovering_parser_without_error() {
+    let l = Lexer::new("let a = 1; a + 2;");
+    let mut p = Parser::new(l);
+    let (program, errors) = p.parse_program_recovering();
+
+    assert!(errors.is_empty());
+    assert_eq!(program.statements.len(), 2);
+}
+
+#[test]
+fn test_render_diagnostic() {
+    let input = "let x = 1;\nlet y = (x + 2;\n";
+    let l = Lexer::with_file(input, "main.mk");
+    let mut p = Parser::new(l);
+    let err = p.parse_program().unwrap_err();
+
+    assert_eq!(
+        diagnostic::render(&err, input),
+        "error: Right parentheses expected
+ --> main.mk:2:15
+  |
+2 | let y = (x + 2;
+  |               ^ expected `)`, found `;`
+"
+    );
+}
+
+#[test]
+fn test_render_foreign_span() {
+    let input = "let x = 1;\nlet y = (x + 2;\n";
+    let err = Parser::new(Lexer::with_file(input, "main.mk"))
+        .parse_program()
+        .unwrap_err();
+
+    // span来自另一段源码时只输出位置，不能按字节偏移切分source
+    for other in ["\"éééééééééééééééééééé\"", "", "let x = 1;\nlet y = 2;\n"] {
+        assert_eq!(
+            diagnostic::render(&err, other),
+            "error: Right parentheses expected\n --> main.mk:2:15\n",
+            "{}",
+            other
+        );
+    }
+}
+
+#[test]
+fn test_identifier_like_keyword() {
+    // 和关键字相近的名字是普通的标识符，拼写错误在执行时才会报告
+    let l = Lexer::new("let form = 1\nform\nlett x = 5;");
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+    assert_eq!(program.to_string(), "let form = 1;formlett(x = 5)");
+}
+
+#[test]
+fn test_suggest() {
+    let candidates = ["len", "first", "last", "rest"];
+    assert_eq!(diagnostic::suggest("lenn", candidates), Some("len"));
+    assert_eq!(diagnostic::suggest("fist", candidates), Some("first"));
+    assert_eq!(diagnostic::suggest("foobar", candidates), None);
+}
+
+#[test]
+fn test_loop_statement() {
+    let cases = [
+        ("while (x < 10) { x; }", "while ((x < 10)) {x}"),
+        (
+            "while (true) { break; continue }",
+            "while (true) {break;continue;}",
+        ),
+        ("for (x in [1, 2]) { x }", "for (x in [1, 2]) {x}"),
+        (
+            "for (i in 0..n + 1) { i };",
+            "for (i in (0 .. (n + 1))) {i}",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse_program().unwrap();
+        assert_eq!(program.to_string(), expect);
+    }
+
+    let l = Lexer::new("for (1 in x) {}");
+    let mut p = Parser::new(l);
+    let err = p.parse_program().unwrap_err();
+    assert_eq!(err.to_string(), "1:6: identifier expected after 'for ('.");
+}
+
+#[test]
+fn test_try_statement() {
+    let cases = [
+        ("throw error;", "throw error;"),
+        ("throw \"boom\";", "throw \"boom\";"),
+        (
+            "try { f() } catch (e) { e } finally { done() }",
+            "try {f()} catch (e) {e} finally {done()}",
+        ),
+        (
+            "try { f() } catch (e) { throw e };",
+            "try {f()} catch (e) {throw e;}",
+        ),
+        ("try { f() } finally { }", "try {f()} finally {}"),
+    ];
+
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse_program().unwrap();
+        assert_eq!(program.to_string(), expect);
+    }
+
+    let cases = [
+        (
+            "try { f() }",
+            "1:12: 'catch' or 'finally' expected after try block.",
+        ),
+        ("try { f() } catch { }", "1:19: '(' expected after 'catch'."),
+        (
+            "try { f() } catch (1) { }",
+            "1:20: identifier expected after 'catch ('.",
+        ),
+        ("try f()", "1:5: '{' expected after 'try'."),
+    ];
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let err = p.parse_program().unwrap_err();
+        assert_eq!(err.to_string(), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_assign_expression() {
+    let cases = [
+        ("x = 5", "(x = 5)"),
+        ("x += 1 + 2", "(x += (1 + 2))"),
+        ("a = b = c", "(a = (b = c))"),
+        ("arr[i] -= 1", "((arr[i]) -= 1)"),
+        ("m[\"k\"][0] *= 2 || y", "(((m[\"k\"])[0]) *= (2 || y))"),
+        ("x /= 2;", "(x /= 2)"),
+    ];
+
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse_program().unwrap();
+        assert_eq!(program.to_string(), expect);
+    }
+
+    let l = Lexer::new("f() = 1");
+    let mut p = Parser::new(l);
+    let err = p.parse_program().unwrap_err();
+    assert_eq!(err.to_string(), "1:1: invalid assignment target: f()");
+}
+
+#[test]
+fn test_const_statement() {
+    let l = Lexer::new("const x = 1; let y = x;");
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+    assert_eq!(program.to_string(), "const x = 1;let y = x;");
+
+    let cases = [
+        ("const x = 1; x = 2", "1:14: cannot assign to constant: x"),
+        (
+            "const x = [1]; x[0] += 2",
+            "1:16: cannot assign to constant: x",
+        ),
+        (
+            "const x = 1; let f = fn() { x = 2 }",
+            "1:29: cannot assign to constant: x",
+        ),
+        (
+            "const x = 1; let x = 2",
+            "1:18: cannot redeclare constant: x",
+        ),
+        ("const = 1", "1:7: identifier expected after 'const'."),
+    ];
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let err = p.parse_program().unwrap_err();
+        assert_eq!(err.to_string(), expect, "{}", input);
+    }
+
+    // 内层作用域中的同名let、函数参数和循环变量遮蔽了外层的const
+    let cases = [
+        "const x = 1; if (true) { let x = 2; x = 3 }",
+        "const x = 1; let f = fn(x) { x = 2 }",
+        "const x = 1; for (x in [1]) { x += 1 }",
+    ];
+    for input in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        assert!(p.parse_program().is_ok(), "{}", input);
+    }
+}
+
+#[test]
+fn test_function_statement() {
+    let cases = [
+        ("fn add(a, b) { a + b }", "fn add(a, b) {(a + b)}"),
+        ("fn f() { return 1; }; f()", "fn f() {return 1;}f()"),
+    ];
+
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse_program().unwrap();
+        assert_eq!(program.to_string(), expect);
+    }
+
+    let l = Lexer::new("const f = 1; fn f() { 2 }");
+    let mut p = Parser::new(l);
+    let err = p.parse_program().unwrap_err();
+    assert_eq!(err.to_string(), "1:17: cannot redeclare constant: f");
+}
+
+#[test]
+fn test_parameters_and_arguments() {
+    let cases = [
+        (
+            "fn f(a, b = 1 + 1, ...rest) {}",
+            "fn f(a, b = (1 + 1), ...rest) {}",
+        ),
+        ("f(1, ...xs, b: 3, c: [1])", "f(1, ...xs, b: 3, c: [1])"),
+        ("f(...0..3)", "f(...(0 .. 3))"),
+    ];
+
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse_program().unwrap();
+        assert_eq!(program.to_string(), expect);
+    }
+
+    let cases = [
+        (
+            "fn(...a, b) {}",
+            "1:8: rest parameter must be the last parameter.",
+        ),
+        (
+            "fn(...a = 1) {}",
+            "1:9: rest parameter cannot have a default value.",
+        ),
+        ("fn(a, a) {}", "1:7: duplicate parameter: a"),
+        ("fn(1) {}", "1:4: parameter name expected."),
+        (
+            "f(a: 1, 2)",
+            "1:9: positional argument cannot follow named arguments.",
+        ),
+        ("f(a: 1, a: 2)", "1:9: duplicate argument: a"),
+    ];
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let err = p.parse_program().unwrap_err();
+        assert_eq!(err.to_string(), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_illegal_token_error() {
+    let source = "let s = \"abc;\nlet t = 1;";
+    let mut p = Parser::new(Lexer::new(source));
+    let err = p.parse_program().unwrap_err();
+    assert_eq!(err.to_string(), "1:9: unterminated string literal");
+    assert_eq!(err.kind(), ErrorKind::IllegalToken);
+    assert_eq!(
+        diagnostic::render(&err, source),
+        "error: unterminated string literal\n --> 1:9\n  |\n1 | let s = \"abc;\n  |         ^^^^^ not a valid token\n"
+    );
+}
+
+#[test]
+fn test_template_literal() {
+    let cases = [
+        ("`Hello ${name}!`", "`Hello ${name}!`"),
+        ("`${a}${b * 2}`", "`${a}${(b * 2)}`"),
+        ("`cost: \\$${n}`", "`cost: \\$${n}`"),
+        ("``", "``"),
+        ("`outer ${`inner ${x}`}`", "`outer ${`inner ${x}`}`"),
+    ];
+
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse_program().unwrap();
+        assert_eq!(program.to_string(), expect);
+    }
+
+    let cases = [
+        (
+            "let s = `a ${}`;",
+            "1:14: empty expression in template literal",
+        ),
+        ("`a ${1 2}`", "1:8: '}' expected after template expression."),
+        ("`a\n${-}`", "2:4: no prefix parse function for EOF"),
+    ];
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let err = p.parse_program().unwrap_err();
+        assert_eq!(err.to_string(), expect, "{}", input);
+    }
+}
+
+#[test]
+fn test_statement_display() {
+    let cases = [
+        ("return 1.5;", "return 1.5;"),
+        ("if (a) { 1 } else { 2 }", "if (a) {1} else {2}"),
+        (
+            "let f = fn(x) { return x * 2; };",
+            "let f = fn(x) {return (x * 2);};",
+        ),
+    ];
+
+    for (input, expect) in cases {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse_program().unwrap();
+        assert_eq!(program.to_string(), expect);
+    }
+}
+
+#[test]
+fn test_format() {
+    let cases = [
+        ("let x=1+2*3", "let x = 1 + 2 * 3;\n"),
+        ("(1 + 2) * 3; 2 - (3 - 4); (2 ** 3) ** 2; 2 ** 3 ** 2", "(1 + 2) * 3;\n2 - (3 - 4);\n(2 ** 3) ** 2;\n2 ** 3 ** 2;\n"),
+        ("-(-x); !(-x); -(f(x)); (-f)(x); -a[0]", "-(-x);\n!-x;\n-(f(x));\n(-f)(x);\n-a[0];\n"),
+        ("a = b = 1; x += (y = 2)", "a = b = 1;\nx += y = 2;\n"),
+        ("for (i in 0 .. n) { puts(i) }", "for (i in 0..n) {\n    puts(i)\n}\n"),
+        (
+            "fn f(a, b = {}, ...rest) { return a; }",
+            "fn f(a, b = {}, ...rest) {\n    return a;\n}\n",
+        ),
+        (
+            "if (a) { 1 } else if (b) { 2 } else { if (c) { 3 } }",
+            "if (a) {\n    1\n} else if (b) {\n    2\n} else {\n    if (c) {\n        3\n    }\n}\n",
+        ),
+        ("while (true) { break; }; []; {}", "while (true) {\n    break;\n}\n[];\n{};\n"),
+        (r#"puts("a\"b\n", `x\`${y}\${z}$`)"#, "puts(\"a\\\"b\\n\", `x\\`${y}\\${z}$`);\n"),
+        ("f(...args, b: 1.0, c: fn() {})", "f(...args, b: 1.0, c: fn() {});\n"),
+        // 非ASCII字符原样保留，只转义词法分析器要求转义的字符
+        (
+            "let s = \"❤️ café\"; puts(\"日本語 👍🏽\", \"tab\\there\\u{7}\")",
+            "let s = \"❤️ café\";\nputs(\"日本語 👍🏽\", \"tab\\there\\u{7}\");\n",
+        ),
+        ("1e20; 1.5e-7; 123456789012345678901.0", "1e20;\n1.5e-7;\n1.2345678901234568e20;\n"),
+        (
+            "try { f() } catch (e) { throw e } finally { }; (1)",
+            "try {\n    f()\n} catch (e) {\n    throw e;\n} finally {}\n1;\n",
+        ),
+        (
+            "try {\n  // 只有注释\n} finally { done() /* 清理 */ }",
+            "try {\n    // 只有注释\n} finally {\n    done() /* 清理 */\n}\n",
+        ),
+        ("", ""),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(format::format(input).unwrap(), expect, "input: {}", input);
+    }
+
+    let err = format::format("let x = ;").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "1:9: no prefix parse function for Semicolon"
+    );
+    // 超出范围的浮点数字面量在词法分析时就是错误，不会被格式化成inf
+    let err = format::format("let x = 1e400;").unwrap_err();
+    assert_eq!(err.to_string(), "1:9: float literal out of range: 1e400");
+}
+
+#[test]
+fn test_format_if_semicolon() {
+    // if语句后面一般不需要';'，但后一条语句以'('、'['开头时需要，否则会被当成调用或下标
+    let input = "if (a) { b };\n(a + b) * c;\nif (a) { b };\n[1];\nif (a) { b }\nd;";
+    assert_eq!(
+        format::format(input).unwrap(),
+        "if (a) {\n    b\n};\n(a + b) * c;\nif (a) {\n    b\n};\n[1];\nif (a) {\n    b\n}\nd;\n"
+    );
+}
+
+#[test]
+fn test_format_comments() {
+    let input = "#!/usr/bin/env monkey
+// 开头的注释
+
+let a = 1;   // 尾注释
+/* 块注释 */ let b = [1, /* 元素之间 */ 2];
+
+
+fn f() {
+  // 函数中的注释
+  a   /* 值 */
+  // 函数末尾的注释
+}
+let g = fn() { /* 空函数 */ };
+if (a) {
+    // then
+} else {
+    // else
+}
+// 最后的注释
+";
+    let expect = "#!/usr/bin/env monkey
+// 开头的注释
+
+let a = 1; // 尾注释
+/* 块注释 */
+let b = [
+    1,
+    /* 元素之间 */ 2
+];
+
+fn f() {
+    // 函数中的注释
+    a /* 值 */
+    // 函数末尾的注释
+}
+let g = fn() {
+    /* 空函数 */
+};
+if (a) {
+    // then
+} else {
+    // else
+}
+// 最后的注释
+";
+    assert_eq!(format::format(input).unwrap(), expect);
+    assert_eq!(format::format(expect).unwrap(), expect);
+}
+
+#[test]
+fn test_format_list_comments() {
+    // 列表中有注释时每个元素占一行，注释留在相邻的元素旁边
+    let input = "let h = {
+ \"a\": 1, // first
+ // lone
+ \"b\": 2
+};
+f(1, /* mid */ 2);
+fn g(a, // the a
+  b) { [ /* none */ ] }
+let x = foo(fn() {
+  // body
+  1
+}, 2 /* two */);
+";
+    let expect = "let h = {
+    \"a\": 1, // first
+    // lone
+    \"b\": 2
+};
+f(
+    1,
+    /* mid */ 2
+);
+fn g(
+    a, // the a
+    b
+) {
+    [
+        /* none */
+    ]
+}
+let x = foo(
+    fn() {
+        // body
+        1
+    },
+    2 /* two */
+);
+";
+    assert_eq!(format::format(input).unwrap(), expect);
+    assert_eq!(format::format(expect).unwrap(), expect);
+    // 模板字符串中的列表没有对应的token，不会拿到模板之后的注释
+    assert_eq!(
+        format::format("`${[1, 2]}`; [3, /* c */ 4]").unwrap(),
+        "`${[1, 2]}`;\n[\n    3,\n    /* c */ 4\n];\n"
+    );
+}
+
+#[test]
+fn test_format_wrapping() {
+    let input = "let result = some_function(first_argument_value, second_argument_value, third_argument_value, 40000);
+let short = [1, 2, 3];
+let nested = {\"key\": [first_element_value, second_element_value, third_element_value, fourth_element]};
+map(items, fn(x) { x * 2 });";
+    let expect = "let result = some_function(
+    first_argument_value,
+    second_argument_value,
+    third_argument_value,
+    40000
+);
+let short = [1, 2, 3];
+let nested = {\"key\": [
+    first_element_value,
+    second_element_value,
+    third_element_value,
+    fourth_element
+]};
+map(items, fn(x) {
+    x * 2
+});
+";
+    let formatted = format::format(input).unwrap();
+    assert_eq!(formatted, expect);
+    assert!(formatted
+        .lines()
+        .all(|line| line.len() <= format::MAX_WIDTH));
+}
+
+#[test]
+fn test_format_keeps_program() {
+    let inputs = [
+        "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(10)",
+        "let a = [1, 2 * (3 + 4), -(-5), !true, \"s\", null, 1.5e10, {1: 2}[1]];",
+        "const m = {\"f\": fn(x, y = x ** 2 ** 3) { x..y }}; m[\"f\"](1)(2)[0]",
+        "let s = `${ {\"a\": [1, 2]}[\"a\"][0] } and ${fn() { 1 }()}`; s",
+        "fn g(a, ...b) { for (x in b) { if (x > a) { continue; } else { a -= x; } } a } g(...[1, 2], b: 3)",
+        "while ((i = i + 1) < 10) { if (i % 2 == 0) { break } }",
+    ];
+
+    for input in inputs {
+        let formatted = format::format(input).unwrap();
+        let expect = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let program = Parser::new(Lexer::new(formatted.as_str()))
+            .parse_program()
+            .unwrap();
+        assert_eq!(program.statements, expect.statements, "{}", formatted);
+        assert_eq!(format::format(&formatted).unwrap(), formatted);
+    }
+}
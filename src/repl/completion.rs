@@ -0,0 +1,73 @@
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use crate::lexer::lexer::is_identifier_char;
+use crate::repl::COMMANDS;
+
+/// 行编辑器的补全，names是当前可见的名字，每次读取输入之前更新
+#[derive(Default)]
+pub struct MonkeyHelper {
+    pub names: Vec<String>,
+}
+
+impl Completer for MonkeyHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(line, pos, &self.names))
+    }
+}
+
+impl Hinter for MonkeyHelper {
+    type Hint = String;
+}
+
+impl Highlighter for MonkeyHelper {}
+
+impl Validator for MonkeyHelper {}
+
+impl Helper for MonkeyHelper {}
+
+/// 补全光标前的名字，行首以':'开头时补全REPL命令。
+/// 返回被替换部分的起始位置和排好序的候选项
+pub fn complete(line: &str, pos: usize, names: &[String]) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+
+    let trimmed = before.trim_start();
+    if trimmed.starts_with(':') && !trimmed.contains(char::is_whitespace) {
+        let candidates = COMMANDS
+            .iter()
+            .map(|(usage, _)| usage.split(' ').next().unwrap_or(usage))
+            .filter(|name| name.starts_with(trimmed))
+            .map(|name| name.to_string())
+            .collect();
+        return (pos - trimmed.len(), candidates);
+    }
+
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, ch)| is_identifier_char(*ch))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let word = &before[start..];
+    if word.is_empty() {
+        return (pos, vec![]);
+    }
+    let mut candidates = names
+        .iter()
+        .filter(|name| name.starts_with(word))
+        .cloned()
+        .collect::<Vec<String>>();
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+use crate::error::{Error, Result};
+use crate::eval::builtins::Builtins;
+use crate::eval::environment::Environment;
+use crate::eval::evaluator::Evaluator;
+use crate::eval::ObjectWrapper;
+use crate::lexer::lexer::Lexer;
+use crate::lexer::token::{Token, KEYWORDS};
+use crate::parser::{diagnostic, Parser};
+use crate::repl::completion::MonkeyHelper;
+
+pub mod completion;
+#[cfg(test)]
+mod test;
+
+pub const PROMPT: &str = ">>";
+/// 输入还没有结束时的提示符
+pub const CONTINUATION_PROMPT: &str = "..";
+/// 放弃已经输入的多行内容
+pub const CANCEL: &str = ":cancel";
+/// `:ast`的输入不会被执行，用这个名字报告语法错误；执行的每段输入以`<repl:N>`命名
+pub const REPL_FILE: &str = "<repl>";
+
+/// REPL命令和说明
+pub const COMMANDS: &[(&str, &str)] = &[
+    (":env", "list the bindings of this session"),
+    (":reset", "discard all bindings"),
+    (":load FILE", "run FILE in this session"),
+    (":type EXPR", "evaluate EXPR and show the type of its value"),
+    (":ast CODE", "show how CODE is parsed"),
+    (":time CODE", "evaluate CODE and show how long it takes"),
+    (
+        ":cancel",
+        "discard unfinished multi-line input, same as Ctrl+C",
+    ),
+    (":help", "show this help"),
+    (":quit", "exit the REPL, same as Ctrl+D"),
+];
+
+/// 启动交互式的REPL，直到输入`:quit`或者Ctrl+D
+pub fn start() {
+    let mut editor = Editor::<MonkeyHelper>::new();
+    editor.set_helper(Some(MonkeyHelper::default()));
+    let history = history_file();
+    if let Some(path) = &history {
+        // 第一次运行时历史文件还不存在
+        let _ = editor.load_history(path);
+    }
+
+    println!("Welcome to Monkey Language REPL, type `:help` for commands, `:quit` to quit.");
+    let mut repl = Repl::default();
+    let mut input = InputBuffer::default();
+    loop {
+        if let Some(helper) = editor.helper_mut() {
+            helper.names = repl.names();
+        }
+        let line = match editor.readline(input.prompt()) {
+            Ok(line) => line,
+            // Ctrl+C放弃还没有输入完的内容
+            Err(ReadlineError::Interrupted) => {
+                input.cancel();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                break;
+            }
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str());
+        }
+
+        let reply = if input.is_empty() && line.trim_start().starts_with(':') {
+            repl.command(&line)
+        } else {
+            match input.push_line(&line) {
+                Some(source) => repl.run(&source),
+                None => continue,
+            }
+        };
+        match reply {
+            Reply::Output(text) if text.is_empty() => {}
+            Reply::Output(text) => println!("{}", text),
+            Reply::Error(text) => eprint!("{}", text),
+            Reply::Quit => break,
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("error: cannot save history to {}: {}", path.display(), e);
+        }
+    }
+}
+
+// 历史记录保存在$MONKEY_HISTORY，默认是~/.monkey_history
+fn history_file() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("MONKEY_HISTORY") {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".monkey_history"))
+}
+
+/// 交互输入的缓冲区，一段输入没有结束时，后面的行会接在它后面
+#[derive(Default)]
+pub struct InputBuffer {
+    buf: String,
+}
+
+impl InputBuffer {
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// 丢弃还没有输入完的内容
+    pub fn cancel(&mut self) {
+        self.buf.clear();
+    }
+
+    /// 下一行输入应该显示的提示符
+    pub fn prompt(&self) -> &'static str {
+        if self.buf.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        }
+    }
+
+    /// 加入一行输入，输入完整时返回整段源码并清空缓冲区。
+    /// 输入`:cancel`会丢弃缓冲区中的内容
+    pub fn push_line(&mut self, line: &str) -> Option<String> {
+        if line.trim() == CANCEL {
+            self.cancel();
+            return None;
+        }
+        self.buf.push_str(line);
+        if !self.buf.ends_with('\n') {
+            self.buf.push('\n');
+        }
+        if self.buf.trim().is_empty() {
+            self.buf.clear();
+            return None;
+        }
+
+        if is_incomplete(&self.buf) {
+            None
+        } else {
+            Some(std::mem::take(&mut self.buf))
+        }
+    }
+}
+
+/// 输入是否还没有结束：括号没有闭合、字符串或注释没有结束，或者以运算符结尾。
+/// 多出来的右括号这样的错误不算没有结束，交给Parser报告
+pub fn is_incomplete(source: &str) -> bool {
+    let mut lx = Lexer::new(source);
+    let mut depth = 0;
+    let mut last = Token::EOF;
+    loop {
+        let token = lx.next_token().token;
+        match &token {
+            Token::EOF => break,
+            Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+            Token::RParen | Token::RBracket | Token::RBrace => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            }
+            Token::Illegal(reason) => return reason.starts_with("unterminated"),
+            _ => {}
+        }
+        last = token;
+    }
+
+    depth > 0
+        || matches!(
+            last,
+            Token::Assign
+                | Token::PlusAssign
+                | Token::MinusAssign
+                | Token::AsteriskAssign
+                | Token::SlashAssign
+                | Token::Plus
+                | Token::Minus
+                | Token::Bang
+                | Token::Asterisk
+                | Token::Slash
+                | Token::Percent
+                | Token::DoubleAsterisk
+                | Token::TildeSlash
+                | Token::LT
+                | Token::GT
+                | Token::LTE
+                | Token::GTE
+                | Token::Eq
+                | Token::NotEq
+                | Token::And
+                | Token::Or
+                | Token::Comma
+                | Token::Colon
+                | Token::DotDot
+                | Token::Ellipsis
+                | Token::Let
+                | Token::Const
+                | Token::Function
+                | Token::If
+                | Token::Else
+                | Token::While
+                | Token::For
+                | Token::In
+                | Token::Return
+                | Token::Try
+                | Token::Catch
+                | Token::Finally
+                | Token::Throw
+        )
+}
+
+/// 执行一段输入或一条REPL命令的结果
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reply {
+    /// 输出到stdout，为空时不输出
+    Output(String),
+    /// 输出到stderr的错误信息
+    Error(String),
+    Quit,
+}
+
+/// 一次REPL会话，前面输入中的绑定在后面的输入中仍然可见
+#[derive(Default)]
+pub struct Repl {
+    env: Environment,
+    // 执行过的每段输入和`:load`的文件。前面定义的函数出错时，要在定义它的源码中显示错误
+    sources: HashMap<String, String>,
+    inputs: usize,
+}
+
+impl Repl {
+    /// 解析并执行一段输入
+    pub fn eval(&mut self, source: &str) -> Result<ObjectWrapper> {
+        let file = self.next_input();
+        self.eval_in(source, &file)
+    }
+
+    // 每段输入是不同的源码，行号都从1开始，所以各自有不同的名字
+    fn next_input(&mut self) -> String {
+        self.inputs += 1;
+        format!("<repl:{}>", self.inputs)
+    }
+
+    fn eval_in(&mut self, source: &str, file: &str) -> Result<ObjectWrapper> {
+        self.sources.insert(file.to_string(), source.to_string());
+        let program = Parser::new(Lexer::with_file(source, file)).parse_program()?;
+        let mut evaluator = Evaluator::with_env(&program.statements, self.env.clone());
+        evaluator.set_file(file);
+        let ret = evaluator.eval();
+        self.env = evaluator.get_env();
+        ret
+    }
+
+    /// 执行一段输入，输出结果或者带源码片段的错误信息，结果是null时不输出
+    pub fn run(&mut self, source: &str) -> Reply {
+        let file = self.next_input();
+        self.run_in(source, &file)
+    }
+
+    fn run_in(&mut self, source: &str, file: &str) -> Reply {
+        match self.eval_in(source, file) {
+            Ok(ObjectWrapper::Null) => Reply::Output(String::new()),
+            Ok(obj) => Reply::Output(obj.inspect()),
+            Err(e) => Reply::Error(self.render(&e)),
+        }
+    }
+
+    // 错误显示在产生它的源码中，例如调用`:load`的文件中定义的函数时，显示的是那个文件
+    fn render(&self, err: &Error) -> String {
+        let source = err
+            .file()
+            .and_then(|file| self.sources.get(file))
+            .map_or("", |source| source.as_str());
+        diagnostic::render(err, source)
+    }
+
+    /// 执行以':'开头的REPL命令
+    pub fn command(&mut self, line: &str) -> Reply {
+        let line = line.trim();
+        let (name, arg) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        let needs_arg = matches!(name, ":load" | ":type" | ":ast" | ":time");
+        if needs_arg && arg.is_empty() {
+            return Reply::Error(format!("error: `{}` needs an argument\n", name));
+        }
+
+        match name {
+            ":env" => {
+                let mut names = self.env.names();
+                names.sort();
+                let bindings = names
+                    .iter()
+                    .filter_map(|name| {
+                        Some(format!("{} = {}", name, self.env.get(name)?.inspect()))
+                    })
+                    .collect::<Vec<String>>();
+                Reply::Output(bindings.join("\n"))
+            }
+            ":cancel" => Reply::Output(String::new()),
+            ":reset" => {
+                self.env = Environment::default();
+                Reply::Output(String::new())
+            }
+            ":load" => match std::fs::read_to_string(arg) {
+                Ok(source) => self.run_in(&source, arg),
+                Err(e) => Reply::Error(format!("error: cannot read {}: {}\n", arg, e)),
+            },
+            ":type" => match self.eval(arg) {
+                Ok(obj) => Reply::Output(obj.type_str().to_string()),
+                Err(e) => Reply::Error(self.render(&e)),
+            },
+            ":ast" => match Parser::new(Lexer::with_file(arg, REPL_FILE)).parse_program() {
+                Ok(program) => Reply::Output(
+                    program
+                        .statements
+                        .iter()
+                        .map(|st| st.to_string())
+                        .collect::<Vec<String>>()
+                        .join("\n"),
+                ),
+                Err(e) => Reply::Error(diagnostic::render(&e, arg)),
+            },
+            ":time" => {
+                let start = Instant::now();
+                let reply = self.run(arg);
+                let elapsed = start.elapsed();
+                match reply {
+                    Reply::Output(text) if text.is_empty() => {
+                        Reply::Output(format!("time: {:?}", elapsed))
+                    }
+                    Reply::Output(text) => Reply::Output(format!("{}\ntime: {:?}", text, elapsed)),
+                    reply => reply,
+                }
+            }
+            ":help" => Reply::Output(
+                COMMANDS
+                    .iter()
+                    .map(|(usage, help)| format!("{:<12}{}", usage, help))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            ),
+            ":quit" => Reply::Quit,
+            _ => Reply::Error(format!(
+                "error: unknown command `{}`, type `:help` for commands\n",
+                name
+            )),
+        }
+    }
+
+    /// 可以补全的名字：当前会话中的绑定、内置函数和关键字
+    pub fn names(&self) -> Vec<String> {
+        let mut ret = self.env.names();
+        ret.extend(Builtins::instance_ref().names());
+        ret.extend(KEYWORDS.iter().map(|keyword| keyword.to_string()));
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+}
@@ -0,0 +1,178 @@
+use crate::eval::ObjectWrapper;
+use crate::repl::completion::complete;
+use crate::repl::{is_incomplete, InputBuffer, Repl, Reply, CONTINUATION_PROMPT, PROMPT};
+
+#[test]
+fn test_incomplete_input() {
+    let cases = [
+        ("let x = 1;", false),
+        ("let f = fn(x) {", true),
+        ("let f = fn(x) {\n  x + 1\n};", false),
+        ("[1, 2,", true),
+        ("puts(1,\n2", true),
+        ("{\"a\": ", true),
+        ("let s = \"abc", true),
+        ("let s = `a${b", true),
+        ("/* 注释", true),
+        ("1 +", true),
+        ("x ==", true),
+        ("let x =", true),
+        ("if (x) { 1 } else", true),
+        ("a[0] }", false),
+        ("let s = \"\\q\"", false),
+        ("", false),
+    ];
+
+    for (input, expect) in cases {
+        assert_eq!(is_incomplete(input), expect, "input: {}", input);
+    }
+}
+
+#[test]
+fn test_input_buffer() {
+    let mut buf = InputBuffer::default();
+    assert_eq!(buf.prompt(), PROMPT);
+    assert_eq!(buf.push_line("let add = fn(a, b) {\n"), None);
+    assert_eq!(buf.prompt(), CONTINUATION_PROMPT);
+    assert_eq!(buf.push_line("\n"), None);
+    assert_eq!(buf.push_line("  a + b\n"), None);
+    assert_eq!(
+        buf.push_line("};\n"),
+        Some("let add = fn(a, b) {\n\n  a + b\n};\n".to_string())
+    );
+    assert_eq!(buf.prompt(), PROMPT);
+
+    // 空行不会进入多行输入
+    assert_eq!(buf.push_line("\n"), None);
+    assert_eq!(buf.prompt(), PROMPT);
+
+    assert_eq!(buf.push_line("[1, 2,\n"), None);
+    assert_eq!(buf.push_line(":cancel\n"), None);
+    assert_eq!(buf.prompt(), PROMPT);
+    assert_eq!(buf.push_line("3"), Some("3\n".to_string()));
+}
+
+#[test]
+fn test_repl_session() {
+    let mut repl = Repl::default();
+    assert_eq!(
+        repl.eval("let add = fn(a, b) {\n  a + b\n};\n").unwrap(),
+        ObjectWrapper::Null
+    );
+    assert_eq!(repl.eval("add(1, 2)").unwrap(), ObjectWrapper::Integer(3));
+    let err = repl.eval("add(1, 2) +\n  foo").unwrap_err();
+    assert_eq!(err.to_string(), "<repl:3>:2:3: identifier not found: foo");
+
+    // 函数体中的错误报告在定义它的那段输入中
+    repl.eval("let f = fn() {\n  nope\n};").unwrap();
+    assert_eq!(
+        repl.run("1 +\nf()"),
+        Reply::Error(
+            "error: identifier not found: nope
+ --> <repl:4>:2:3
+  |
+2 |   nope
+  |   ^^^^
+traceback (most recent call first):
+  0: f at <repl:5>:2:1
+"
+            .to_string()
+        )
+    );
+}
+
+#[test]
+fn test_repl_commands() {
+    let mut repl = Repl::default();
+    repl.eval("let b = \"x\"; let a = fn(x) { x };").unwrap();
+    assert_eq!(
+        repl.command(":env"),
+        Reply::Output("a = <fn a(x)>\nb = \"x\"".to_string())
+    );
+    assert_eq!(
+        repl.command(":type  a(1.5) "),
+        Reply::Output("float".to_string())
+    );
+    assert_eq!(
+        repl.command(":ast 1 + 2 * 3; -a"),
+        Reply::Output("(1 + (2 * 3))\n(-a)".to_string())
+    );
+    // 字符串字面量带着引号输出，不会和标识符混淆
+    assert_eq!(
+        repl.command(":ast let s = \"a b\" + x; `${\"q\"}`"),
+        Reply::Output("let s = (\"a b\" + x);\n`${\"q\"}`".to_string())
+    );
+    assert!(
+        matches!(repl.command(":time a(1)"), Reply::Output(text) if text.starts_with("1\ntime: "))
+    );
+    assert_eq!(repl.command(":quit"), Reply::Quit);
+    assert_eq!(
+        repl.command(":type"),
+        Reply::Error("error: `:type` needs an argument\n".to_string())
+    );
+    assert_eq!(
+        repl.command(":foo"),
+        Reply::Error("error: unknown command `:foo`, type `:help` for commands\n".to_string())
+    );
+    assert!(
+        matches!(repl.command(":type c"), Reply::Error(text) if text.starts_with("error: identifier not found: c"))
+    );
+
+    assert_eq!(repl.command(":reset"), Reply::Output(String::new()));
+    assert_eq!(repl.command(":env"), Reply::Output(String::new()));
+    assert!(repl.eval("a").is_err());
+}
+
+#[test]
+fn test_repl_load() {
+    let path = std::env::temp_dir().join(format!("monkey_repl_load_{}.mk", std::process::id()));
+    std::fs::write(&path, "let double = fn(x) { x * 2 };\ndouble(2)").unwrap();
+    let mut repl = Repl::default();
+    let reply = repl.command(&format!(":load {}", path.display()));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(reply, Reply::Output("4".to_string()));
+    assert_eq!(repl.eval("double(3)").unwrap(), ObjectWrapper::Integer(6));
+
+    // 在加载的文件中定义的函数出错时，显示的是那个文件中的位置和源码
+    let path = std::env::temp_dir().join(format!("monkey_repl_lib_{}.mk", std::process::id()));
+    std::fs::write(&path, "fn half(x) {\n  x / 0\n}").unwrap();
+    let file = path.display().to_string();
+    let reply = repl.command(&format!(":load {}", file));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(reply, Reply::Output(String::new()));
+    let err = repl.eval("let y = half(4)").unwrap_err();
+    assert_eq!(err.to_string(), format!("{}:2:3: division by zero", file));
+    assert_eq!(err.frames()[0].to_string(), "half at <repl:2>:1:9");
+
+    assert!(
+        matches!(repl.command(":load /no/such/file.mk"), Reply::Error(text) if text.starts_with("error: cannot read /no/such/file.mk"))
+    );
+}
+
+#[test]
+fn test_completion() {
+    let names = Repl::default().names();
+    assert!(names.contains(&"len".to_string()));
+    assert!(names.contains(&"while".to_string()));
+
+    let names = vec![
+        "len".to_string(),
+        "let".to_string(),
+        "player_hp".to_string(),
+        "while".to_string(),
+    ];
+    assert_eq!(
+        complete("le", 2, &names),
+        (0, vec!["len".to_string(), "let".to_string()])
+    );
+    assert_eq!(
+        complete("puts(player_", 12, &names),
+        (5, vec!["player_hp".to_string()])
+    );
+    assert_eq!(complete("1 + ", 4, &names), (4, vec![]));
+    assert_eq!(
+        complete("  :t", 4, &names),
+        (2, vec![":type".to_string(), ":time".to_string()])
+    );
+    assert_eq!(complete(":type le", 8, &names).0, 6);
+}